@@ -0,0 +1,85 @@
+//! Time to parse a 5,000 record response, the largest batch a poll asks for.
+//! Fails when the borrowed parse goes over `BUDGET`.
+//!
+//! `cargo bench --bench parse`
+
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use chrono::Utc;
+use rt_data::tencent::{ParseMode, Record, ResponseParser, Tencent};
+
+const RECORDS: usize = 5_000;
+const ROUNDS: usize = 20;
+/// For `parse_ref_with` over every record, what a sweep at one second
+/// intervals can spend.
+const BUDGET: Duration = Duration::from_millis(1);
+
+const RECORD: &str = "v_sz000001=\"51~平安银行~000001~15.81~15.90~15.90~821772~381881~439892~15.\
+                      81~506~15.80~1439~15.79~2145~15.78~3932~15.77~687~15.82~343~15.83~2665~15.\
+                      84~1449~15.85~2681~15.86~1157~~20220419161403~-0.09~-0.57~15.97~15.62~15.\
+                      81/821772/1294226951~821772~129423~0.42~8.44~~15.97~15.62~2.20~3068.01~3068.\
+                      08~0.94~17.49~14.31~0.79~414~15.75~8.44~8.44~~~1.33~129422.6951~0.0000~0~ \
+                      ~GP-A~-4.07~-0.69~1.14~9.19~0.74~25.16~13.22~0.38~7.55~-3.18~19405522500~\
+                      19405918750~2.43~-23.25~19405522500~\"";
+
+/// The fastest of `ROUNDS` runs of `f` over every record.
+fn best(records: &[String], f: impl Fn(&str)) -> Duration {
+    (0..ROUNDS)
+        .map(|_| {
+            let start = Instant::now();
+            for record in records {
+                f(black_box(record));
+            }
+            start.elapsed()
+        })
+        .min()
+        .unwrap_or_default()
+}
+
+fn main() {
+    let codes = (0..RECORDS)
+        .map(|i| format!("sz{:06}", i))
+        .collect::<Vec<_>>();
+    let records = codes
+        .iter()
+        .map(|code| RECORD.replace("sz000001", code))
+        .collect::<Vec<_>>();
+
+    let split = best(&records, |r| {
+        black_box(Record::parse(r).map(|r| r.len()).ok());
+    });
+    let borrowed = best(&records, |r| {
+        black_box(Tencent::parse_ref_with(r, ParseMode::Lenient).is_ok());
+    });
+    let owned = best(&records, |r| {
+        black_box(Tencent::parse_with(r, ParseMode::Lenient).is_ok());
+    });
+
+    let symbols = codes.iter().map(|c| c.as_str()).collect::<Vec<_>>();
+    let body = records.join(";\n") + ";\n";
+    let response = (0..ROUNDS)
+        .map(|_| {
+            let start = Instant::now();
+            let received_at = Utc::now();
+            let mut parser = ResponseParser::utf8(&symbols);
+            black_box(parser.push(body.as_bytes(), received_at));
+            black_box(parser.finish(received_at));
+            start.elapsed()
+        })
+        .min()
+        .unwrap_or_default();
+
+    println!("{} records, best of {} rounds", RECORDS, ROUNDS);
+    println!("framing and split   {:>12?}", split);
+    println!("parse_ref_with      {:>12?}", borrowed);
+    println!("parse_with          {:>12?}", owned);
+    println!("ResponseParser      {:>12?}", response);
+
+    if borrowed > BUDGET {
+        eprintln!("parse_ref_with is over its {:?} budget", BUDGET);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,16 @@
+//! Any input either parses or fails with a `RecordError`, it never panics.
+//!
+//! `cargo +nightly fuzz run parse_record`
+
+#![no_main]
+
+use chrono::Utc;
+use libfuzzer_sys::fuzz_target;
+use rt_data::tencent::Tencent;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(input) = std::str::from_utf8(data) {
+        let _ = Tencent::parse(input);
+        let _ = Tencent::parse_response(input, &["sz000001", "sh600000"], Utc::now());
+    }
+});
@@ -0,0 +1,196 @@
+use async_trait::async_trait;
+use chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Serialize};
+
+use crate::{error::Result, price::Decimal, symbol::Symbol, GainRTData};
+
+/// A kind of data a source can serve. Every source serves `Quotes` through
+/// `GainRTData`, the others are opt-in traits a source exposes through the
+/// `as_*` methods of `GainRTData`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Capability {
+    Quotes,
+    Bars,
+    Ticks,
+    Search,
+    Fundamentals,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BarPeriod {
+    Minute1,
+    Minute5,
+    Minute15,
+    Minute30,
+    Minute60,
+    Day,
+    Week,
+    Month,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bar {
+    /// The start of the bar, in the exchange's time.
+    pub time:   DateTime<FixedOffset>,
+    pub open:   Decimal,
+    pub high:   Decimal,
+    pub low:    Decimal,
+    pub close:  Decimal,
+    pub volume: i64,
+    pub amount: Decimal,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TickSide {
+    Buy,
+    Sell,
+    Neutral,
+}
+
+/// One trade, from a tick source or the latest trades a quote carries.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Tick {
+    /// In the exchange's time.
+    pub time:   DateTime<FixedOffset>,
+    pub price:  Decimal,
+    pub volume: i64,
+    pub amount: Decimal,
+    pub side:   TickSide,
+    /// The vendor's running number of the trade for the day.
+    pub id:     Option<i64>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchHit {
+    pub symbol: Symbol,
+    pub name:   String,
+}
+
+/// Market values are in units of the quote currency, like `ItemData`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Fundamentals {
+    pub code:                String,
+    pub pe:                  Option<f32>,
+    pub pb:                  Option<f32>,
+    pub market_value:        Option<Decimal>,
+    pub traded_market_value: Option<Decimal>,
+}
+
+/// Historical bars, oldest first.
+#[async_trait]
+pub trait BarSource: Send + Sync {
+    async fn bars(&self, symbol: &Symbol, period: BarPeriod, count: usize) -> Result<Vec<Bar>>;
+}
+
+/// Today's trades, oldest first.
+#[async_trait]
+pub trait TickSource: Send + Sync {
+    async fn ticks(&self, symbol: &Symbol) -> Result<Vec<Tick>>;
+}
+
+/// Symbols matching a code, name or pinyin fragment.
+#[async_trait]
+pub trait SearchSource: Send + Sync {
+    async fn search(&self, query: &str) -> Result<Vec<SearchHit>>;
+}
+
+#[async_trait]
+pub trait FundamentalsSource: Send + Sync {
+    async fn fundamentals(&self, symbol: &Symbol) -> Result<Fundamentals>;
+}
+
+/// The first of `sources` that supports `capability`, for routing a request to
+/// a source that can actually serve it.
+pub fn route(sources: &[Box<dyn GainRTData>], capability: Capability) -> Option<&dyn GainRTData> {
+    sources
+        .iter()
+        .map(|source| source.as_ref())
+        .find(|source| source.supports(capability))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::{realtime_data::QuoteOutcome, tencent::Tencent};
+
+    /// Serves a flat daily bar for any symbol and nothing else.
+    struct Flat;
+
+    #[async_trait]
+    impl GainRTData for Flat {
+        fn name(&self) -> &'static str {
+            "flat"
+        }
+
+        async fn quotes(
+            &self,
+            _stocks_list: Vec<&str>,
+            _prefix: bool,
+        ) -> Result<HashMap<String, QuoteOutcome>> {
+            Ok(HashMap::new())
+        }
+
+        fn as_bars(&self) -> Option<&dyn BarSource> {
+            Some(self)
+        }
+    }
+
+    #[async_trait]
+    impl BarSource for Flat {
+        async fn bars(
+            &self,
+            _symbol: &Symbol,
+            _period: BarPeriod,
+            count: usize,
+        ) -> Result<Vec<Bar>> {
+            let time = FixedOffset::east(8 * 3600)
+                .ymd(2022, 4, 19)
+                .and_hms(0, 0, 0);
+            let bar = Bar {
+                time,
+                open:   Decimal::ONE,
+                high:   Decimal::ONE,
+                low:    Decimal::ONE,
+                close:  Decimal::ONE,
+                volume: 0,
+                amount: Decimal::ZERO,
+            };
+            Ok(vec![bar; count])
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_route() {
+        let sources: Vec<Box<dyn GainRTData>> = vec![Box::new(Tencent::new()), Box::new(Flat)];
+
+        assert_eq!(
+            sources[0].capabilities(),
+            HashSet::from([Capability::Quotes, Capability::Fundamentals])
+        );
+        assert_eq!(
+            sources[1].capabilities(),
+            HashSet::from([Capability::Quotes, Capability::Bars])
+        );
+
+        assert_eq!(
+            route(&sources, Capability::Quotes).unwrap().name(),
+            "tencent"
+        );
+        assert!(route(&sources, Capability::Ticks).is_none());
+
+        let source = route(&sources, Capability::Bars).unwrap();
+        let symbol = "sh600036".parse::<Symbol>().unwrap();
+        let bars = source
+            .as_bars()
+            .unwrap()
+            .bars(&symbol, BarPeriod::Day, 3)
+            .await
+            .unwrap();
+        assert_eq!(bars.len(), 3);
+    }
+}
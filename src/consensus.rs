@@ -0,0 +1,338 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use futures_util::future;
+use log::warn;
+
+use crate::{
+    error::Result,
+    price::AsFloat,
+    realtime_data::{ItemData, QuoteOutcome},
+    GainRTData,
+};
+
+/// The quote fields compared across sources.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ComparedField {
+    Now,
+    Close,
+    Volume,
+    Bid1,
+    Ask1,
+}
+
+/// How far, relative to the median, a source may be off before it's reported.
+#[derive(Clone, Copy, Debug)]
+pub struct Tolerance {
+    pub price:  f64,
+    pub volume: f64,
+}
+
+/// Sources that disagree with the median on one field.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Discrepancy {
+    pub field:    ComparedField,
+    /// The mean of the middle two values for an even number of sources.
+    pub median:   f64,
+    /// `(source, value)` for every source that answered with the field.
+    pub values:   Vec<(String, f64)>,
+    pub outliers: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct ConsensusReport {
+    /// The quote of the source that agreed with the median on the most fields,
+    /// the freshest one on a tie.
+    pub consensus:     Option<ItemData>,
+    pub sources:       Vec<String>,
+    pub discrepancies: Vec<Discrepancy>,
+}
+
+/// Queries every source in parallel and cross-checks their quotes, catching a
+/// vendor that went stale during a fast market.
+pub struct Consensus {
+    sources:   Vec<Box<dyn GainRTData>>,
+    tolerance: Tolerance,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Tolerance {
+            price:  0.001,
+            volume: 0.01,
+        }
+    }
+}
+
+impl ComparedField {
+    const ALL: [ComparedField; 5] = [
+        ComparedField::Now,
+        ComparedField::Close,
+        ComparedField::Volume,
+        ComparedField::Bid1,
+        ComparedField::Ask1,
+    ];
+
+    /// `None` for a book side without orders, which isn't compared.
+    fn value(&self, rt_data: &ItemData) -> Option<f64> {
+        match self {
+            ComparedField::Now => Some(rt_data.now.as_f64()),
+            ComparedField::Close => Some(rt_data.close.as_f64()),
+            ComparedField::Volume => Some(rt_data.volume as f64),
+            ComparedField::Bid1 => rt_data.book.best_bid().map(|l| l.price.as_f64()),
+            ComparedField::Ask1 => rt_data.book.best_ask().map(|l| l.price.as_f64()),
+        }
+    }
+}
+
+impl Consensus {
+    pub fn new(sources: Vec<Box<dyn GainRTData>>, tolerance: Tolerance) -> Consensus {
+        Consensus { sources, tolerance }
+    }
+
+    /// One report per requested symbol. Fails only if every source failed.
+    pub async fn compare(
+        &self,
+        stocks_list: Vec<&str>,
+        prefix: bool,
+    ) -> Result<HashMap<String, ConsensusReport>> {
+        let requests = self
+            .sources
+            .iter()
+            .map(|source| source.quotes(stocks_list.clone(), prefix));
+        let responses = future::join_all(requests).await;
+
+        let mut quotes: HashMap<&str, Vec<ItemData>> = HashMap::new();
+        let mut last_error = None;
+        let mut answered = false;
+        for (source, response) in self.sources.iter().zip(responses) {
+            let mut outcomes = match response {
+                Ok(outcomes) => outcomes,
+                Err(e) => {
+                    warn!("source {} failed: {}", source.name(), e);
+                    last_error = Some(e);
+                    continue;
+                }
+            };
+            answered = true;
+
+            for symbol in &stocks_list {
+                if let Some(QuoteOutcome::Quote(rt_data)) | Some(QuoteOutcome::Suspended(rt_data)) =
+                    outcomes.remove(*symbol)
+                {
+                    quotes.entry(symbol).or_default().push(rt_data);
+                }
+            }
+        }
+
+        if let (false, Some(e)) = (answered, last_error) {
+            return Err(e);
+        }
+
+        Ok(stocks_list
+            .iter()
+            .map(|symbol| {
+                let report = self.report(quotes.remove(symbol).unwrap_or_default());
+                (symbol.to_string(), report)
+            })
+            .collect())
+    }
+
+    fn report(&self, quotes: Vec<ItemData>) -> ConsensusReport {
+        let mut discrepancies = vec![];
+        let mut disagreements = vec![0; quotes.len()];
+
+        for field in ComparedField::ALL {
+            let tolerance = match field {
+                ComparedField::Volume => self.tolerance.volume,
+                _ => self.tolerance.price,
+            };
+
+            let mut values = quotes
+                .iter()
+                .filter_map(|q| field.value(q))
+                .collect::<Vec<_>>();
+            values.sort_by(f64::total_cmp);
+            // with two sources neither is taken as right, both are off the
+            // mean and freshness decides
+            let mid = values.len() / 2;
+            let median = match values.len() {
+                0 => continue,
+                n if n % 2 == 0 => (values[mid - 1] + values[mid]) / 2.0,
+                _ => values[mid],
+            };
+
+            let mut outliers = vec![];
+            for (idx, rt_data) in quotes.iter().enumerate() {
+                let value = match field.value(rt_data) {
+                    Some(value) => value,
+                    None => continue,
+                };
+                if (value - median).abs() > median.abs() * tolerance {
+                    outliers.push(rt_data.source.clone());
+                    disagreements[idx] += 1;
+                }
+            }
+
+            if !outliers.is_empty() {
+                discrepancies.push(Discrepancy {
+                    field,
+                    median,
+                    values: quotes
+                        .iter()
+                        .filter_map(|q| Some((q.source.clone(), field.value(q)?)))
+                        .collect(),
+                    outliers,
+                });
+            }
+        }
+
+        let sources = quotes.iter().map(|q| q.source.clone()).collect();
+        let consensus = quotes
+            .into_iter()
+            .zip(disagreements)
+            .min_by(|(a, a_count), (b, b_count)| {
+                a_count.cmp(b_count).then(b.datatime.cmp(&a.datatime))
+            })
+            .map(|(rt_data, _)| rt_data);
+
+        ConsensusReport {
+            consensus,
+            sources,
+            discrepancies,
+        }
+    }
+}
+
+/// Serves the consensus quote of every symbol, so it can stand in for a
+/// single source.
+#[async_trait]
+impl GainRTData for Consensus {
+    fn name(&self) -> &'static str {
+        "consensus"
+    }
+
+    async fn quotes(
+        &self,
+        stocks_list: Vec<&str>,
+        prefix: bool,
+    ) -> Result<HashMap<String, QuoteOutcome>> {
+        let reports = self.compare(stocks_list, prefix).await?;
+
+        Ok(reports
+            .into_iter()
+            .map(|(symbol, report)| {
+                let outcome = match report.consensus {
+                    Some(rt_data) => QuoteOutcome::Quote(rt_data),
+                    None => QuoteOutcome::NotFound,
+                };
+                (symbol, outcome)
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::naive::NaiveDateTime;
+    use rust_decimal_macros::dec;
+
+    use super::*;
+    use crate::{
+        order_book::{Level, OrderBook},
+        price::Decimal,
+        symbol::Market,
+        testing::FakeSource,
+    };
+
+    fn source(name: &'static str, now: Decimal, volume: i64, time: &str) -> Box<dyn GainRTData> {
+        FakeSource::new(name)
+            .with_quote(quote(now, volume, time))
+            .boxed()
+    }
+
+    fn quote(now: Decimal, volume: i64, time: &str) -> ItemData {
+        ItemData {
+            now,
+            close: dec!(15.90),
+            volume,
+            datatime: NaiveDateTime::parse_from_str(time, "%Y%m%d%H%M%S")
+                .ok()
+                .and_then(|dt| Market::China.localize(&dt)),
+            ..Default::default()
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_consensus() {
+        let consensus = Consensus::new(
+            vec![
+                source("stale", dec!(15.60), 80000000, "20220419100000"),
+                source("a", dec!(15.81), 82177200, "20220419100003"),
+                source("b", dec!(15.81), 82177200, "20220419100004"),
+            ],
+            Tolerance::default(),
+        );
+
+        let mut reports = consensus.compare(vec!["sz000001"], true).await.unwrap();
+        let report = reports.remove("sz000001").unwrap();
+
+        assert_eq!(report.sources, vec!["stale", "a", "b"]);
+        assert_eq!(report.discrepancies.len(), 2);
+        assert_eq!(report.discrepancies[0].field, ComparedField::Now);
+        assert_eq!(report.discrepancies[0].outliers, vec!["stale"]);
+        assert_eq!(report.discrepancies[1].field, ComparedField::Volume);
+        assert_eq!(report.consensus.unwrap().source, "b");
+
+        let quotes = consensus.stocks(vec!["sz000001"], true).await.unwrap();
+        assert_eq!(quotes[0].now, dec!(15.81));
+    }
+
+    #[actix_web::test]
+    async fn test_two_sources() {
+        let consensus = Consensus::new(
+            vec![
+                source("a", dec!(15.60), 82177200, "20220419100000"),
+                source("b", dec!(15.81), 82177200, "20220419100004"),
+            ],
+            Tolerance::default(),
+        );
+
+        let mut reports = consensus.compare(vec!["sz000001"], true).await.unwrap();
+        let report = reports.remove("sz000001").unwrap();
+
+        assert_eq!(report.discrepancies.len(), 1);
+        assert!((report.discrepancies[0].median - 15.705).abs() < 1e-9);
+        assert_eq!(report.discrepancies[0].outliers, vec!["a", "b"]);
+        assert_eq!(report.consensus.unwrap().source, "b");
+    }
+    #[actix_web::test]
+    async fn test_missing_levels() {
+        // one source without bids isn't taken as bidding zero
+        let with_book = |name| {
+            let mut quote = quote(dec!(15.81), 82177200, "20220419100003");
+            quote.book = OrderBook {
+                bids: vec![Level {
+                    price:  dec!(15.81),
+                    volume: 50600,
+                }],
+                asks: vec![],
+            };
+            FakeSource::new(name).with_quote(quote).boxed()
+        };
+        let consensus = Consensus::new(
+            vec![
+                with_book("a"),
+                with_book("b"),
+                source("no book", dec!(15.81), 82177200, "20220419100004"),
+            ],
+            Tolerance::default(),
+        );
+
+        let mut reports = consensus.compare(vec!["sz000001"], true).await.unwrap();
+        let report = reports.remove("sz000001").unwrap();
+
+        assert!(report.discrepancies.is_empty());
+        assert_eq!(report.consensus.unwrap().source, "no book");
+    }
+}
@@ -0,0 +1,312 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    future,
+};
+
+use chrono::{DateTime, FixedOffset};
+use futures_util::{Stream, StreamExt};
+
+use crate::{
+    capability::Tick,
+    order_book::Side,
+    price::Decimal,
+    realtime_data::{FieldValue, ItemData, Performance},
+    status::{RiskFlag, TradingStatus},
+    symbol::InstrumentType,
+};
+
+/// One field of a quote that changed, with its new value. Book levels count
+/// from 1, a level that emptied out is reported with zero price and volume.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FieldChange {
+    Name(String),
+    DisplayName(String),
+    Now(Decimal),
+    Close(Decimal),
+    Open(Decimal),
+    Volume(i64),
+    BidVolume(i64),
+    AskVolume(i64),
+    Bid {
+        level:  usize,
+        price:  Decimal,
+        volume: i64,
+    },
+    Ask {
+        level:  usize,
+        price:  Decimal,
+        volume: i64,
+    },
+    Datatime(Option<DateTime<FixedOffset>>),
+    GainAmount(Decimal),
+    GainPercentage(f32),
+    High(Decimal),
+    Low(Decimal),
+    TotalValue(Decimal),
+    Turnover(Option<f32>),
+    Pe(Option<f32>),
+    Pb(Option<f32>),
+    Amplitude(f32),
+    TradedMarketValue(Option<Decimal>),
+    MarketValue(Option<Decimal>),
+    HighLimit(Decimal),
+    LowLimit(Decimal),
+    QuantityRelativeRatio(Option<f32>),
+    EntrustDifferent(Option<i64>),
+    AveragePrice(Option<Decimal>),
+    PeDynamic(Option<f32>),
+    PeStatic(Option<f32>),
+    High52w(Option<Decimal>),
+    Low52w(Option<Decimal>),
+    CirculatingShares(Option<i64>),
+    TotalShares(Option<i64>),
+    VendorType(String),
+    InstrumentType(InstrumentType),
+    Currency(String),
+    Performance(Performance),
+    RecentTrades(Vec<Tick>),
+    VendorFields(BTreeMap<usize, FieldValue>),
+    Status(TradingStatus),
+    RiskFlags(BTreeSet<RiskFlag>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct QuoteDelta {
+    pub code:    String,
+    pub changes: Vec<FieldChange>,
+}
+
+/// Remembers the last snapshot of every code and reports what changed.
+#[derive(Default)]
+pub struct ChangeDetector {
+    last: HashMap<String, ItemData>,
+}
+
+impl ChangeDetector {
+    pub fn new() -> ChangeDetector {
+        ChangeDetector::default()
+    }
+
+    /// Returns the changes since the previous snapshot of the same code, or
+    /// `None` if nothing changed. The first snapshot of a code is compared to
+    /// `ItemData::default()`, so a consumer starting from defaults ends up
+    /// with the full quote.
+    ///
+    /// During a session vendors restamp every code with each exchange
+    /// snapshot, whether it traded or not, so a new `datatime` alone doesn't
+    /// count as a change; it is only reported alongside others. A suspended
+    /// code keeps its old stamp and reports nothing.
+    pub fn update(&mut self, rt_data: ItemData) -> Option<QuoteDelta> {
+        let changes = match self.last.get(&rt_data.code) {
+            Some(last) => diff(last, &rt_data),
+            None => diff(&ItemData::default(), &rt_data),
+        };
+        let code = rt_data.code.clone();
+        self.last.insert(code.clone(), rt_data);
+
+        if changes
+            .iter()
+            .all(|c| matches!(c, FieldChange::Datatime(_)))
+        {
+            return None;
+        }
+
+        Some(QuoteDelta { code, changes })
+    }
+
+    /// Drops the snapshot of `code`, its next update is reported in full.
+    pub fn forget(&mut self, code: &str) {
+        self.last.remove(code);
+    }
+}
+
+/// Turns a stream of snapshots, such as a `Subscription`, into a stream of
+/// deltas that skips unchanged snapshots.
+pub fn changes<S>(snapshots: S) -> impl Stream<Item = QuoteDelta>
+where
+    S: Stream<Item = ItemData>,
+{
+    let mut detector = ChangeDetector::new();
+    snapshots.filter_map(move |rt_data| future::ready(detector.update(rt_data)))
+}
+
+/// Field-by-field comparison of two snapshots of the same code.
+pub fn diff(old: &ItemData, new: &ItemData) -> Vec<FieldChange> {
+    let mut changes = vec![];
+
+    macro_rules! fields {
+        ($($field:ident => $change:ident),* $(,)?) => {
+            $(
+                if old.$field != new.$field {
+                    changes.push(FieldChange::$change(new.$field.clone()));
+                }
+            )*
+        };
+    }
+
+    fields!(
+        name => Name,
+        display_name => DisplayName,
+        now => Now,
+        close => Close,
+        open => Open,
+        volume => Volume,
+        bid_volume => BidVolume,
+        ask_volume => AskVolume,
+    );
+
+    for side in [Side::Bid, Side::Ask] {
+        let (old_levels, new_levels) = (old.book.side(side), new.book.side(side));
+        for idx in 0..old_levels.len().max(new_levels.len()) {
+            let old_level = old_levels.get(idx).copied().unwrap_or_default();
+            let new_level = new_levels.get(idx).copied().unwrap_or_default();
+            if old_level == new_level {
+                continue;
+            }
+
+            let (level, price, volume) = (idx + 1, new_level.price, new_level.volume);
+            changes.push(match side {
+                Side::Bid => FieldChange::Bid {
+                    level,
+                    price,
+                    volume,
+                },
+                Side::Ask => FieldChange::Ask {
+                    level,
+                    price,
+                    volume,
+                },
+            });
+        }
+    }
+
+    fields!(
+        datatime => Datatime,
+        gain_amount => GainAmount,
+        gain_percentage => GainPercentage,
+        high => High,
+        low => Low,
+        total_value => TotalValue,
+        turnover => Turnover,
+        pe => Pe,
+        pb => Pb,
+        amplitude => Amplitude,
+        traded_market_value => TradedMarketValue,
+        market_value => MarketValue,
+        high_limit => HighLimit,
+        low_limit => LowLimit,
+        quantity_relative_ratio => QuantityRelativeRatio,
+        entrust_different => EntrustDifferent,
+        average_price => AveragePrice,
+        pe_dynamic => PeDynamic,
+        pe_static => PeStatic,
+        high_52w => High52w,
+        low_52w => Low52w,
+        circulating_shares => CirculatingShares,
+        total_shares => TotalShares,
+        vendor_type => VendorType,
+        instrument_type => InstrumentType,
+        currency => Currency,
+        performance => Performance,
+        recent_trades => RecentTrades,
+        vendor_fields => VendorFields,
+        status => Status,
+        risk_flags => RiskFlags,
+    );
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::naive::NaiveDateTime;
+    use futures_util::stream;
+    use rust_decimal_macros::dec;
+
+    use super::*;
+    use crate::{order_book::Level, symbol::Market};
+
+    fn time(s: &str) -> Option<DateTime<FixedOffset>> {
+        Market::China.localize(&NaiveDateTime::parse_from_str(s, "%Y%m%d%H%M%S").ok()?)
+    }
+
+    fn quote(now: Decimal, bid2_volume: i64, datatime: &str) -> ItemData {
+        let mut rt_data = ItemData {
+            code: "sh600036".to_string(),
+            now,
+            datatime: time(datatime),
+            ..Default::default()
+        };
+        rt_data.book.bids = vec![
+            Level {
+                price:  dec!(15.81),
+                volume: 100,
+            },
+            Level {
+                price:  dec!(15.80),
+                volume: bid2_volume,
+            },
+        ];
+        rt_data
+    }
+
+    #[test]
+    fn test_change_detector() {
+        let mut detector = ChangeDetector::new();
+
+        let first = detector
+            .update(quote(dec!(15.81), 100, "20220419100000"))
+            .unwrap();
+        assert_eq!(first.code, "sh600036");
+        assert_eq!(first.changes.len(), 4);
+
+        // restamped by the next snapshot, or not at all when suspended
+        assert_eq!(
+            detector.update(quote(dec!(15.81), 100, "20220419100001")),
+            None
+        );
+        assert_eq!(
+            detector.update(quote(dec!(15.81), 100, "20220419100001")),
+            None
+        );
+
+        let delta = detector
+            .update(quote(dec!(15.82), 200, "20220419100002"))
+            .unwrap();
+        assert_eq!(
+            delta.changes,
+            vec![
+                FieldChange::Now(dec!(15.82)),
+                FieldChange::Bid {
+                    level:  2,
+                    price:  dec!(15.80),
+                    volume: 200,
+                },
+                FieldChange::Datatime(time("20220419100002")),
+            ]
+        );
+
+        detector.forget("sh600036");
+        assert_eq!(
+            detector
+                .update(quote(dec!(15.82), 200, "20220419100002"))
+                .unwrap()
+                .changes
+                .len(),
+            4
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_changes_stream() {
+        let snapshots = stream::iter(vec![
+            quote(dec!(15.81), 100, "20220419100000"),
+            quote(dec!(15.81), 100, "20220419100001"),
+            quote(dec!(15.82), 100, "20220419100002"),
+        ]);
+
+        let deltas = changes(snapshots).collect::<Vec<_>>().await;
+        assert_eq!(deltas.len(), 2);
+        assert_eq!(deltas[1].changes[0], FieldChange::Now(dec!(15.82)));
+    }
+}
@@ -0,0 +1,79 @@
+use std::fmt;
+
+use reqwest::StatusCode;
+use thiserror::Error;
+
+use crate::symbol::Exchange;
+
+pub type Result<T> = std::result::Result<T, RtError>;
+
+#[derive(Error, Debug)]
+pub enum RtError {
+    #[error("transport error: {0}")]
+    Transport(#[source] reqwest::Error),
+
+    #[error("decode response body error: {0}")]
+    Decode(#[source] reqwest::Error),
+
+    #[error("parse error: {0}")]
+    Parse(String),
+
+    #[error("unknown symbol: {0}")]
+    UnknownSymbol(String),
+
+    #[error("invalid symbol: {0:?}")]
+    InvalidSymbol(String),
+
+    #[error("ambiguous symbol {symbol}, could be any of {candidates:?}")]
+    AmbiguousSymbol {
+        symbol:     String,
+        candidates: Vec<Exchange>,
+    },
+
+    /// A response that isn't quotes: one with an error status, or a successful
+    /// one whose body is a block page or the like.
+    #[error("{url} answered {status} with {kind} instead of quotes: {excerpt:?}")]
+    Blocked {
+        kind:    BlockKind,
+        status:  StatusCode,
+        url:     String,
+        /// The start of the body, decoded as well as it could be.
+        excerpt: String,
+    },
+
+    #[error("no source registered as {0:?}")]
+    UnknownSource(String),
+
+    #[error("a source is already registered as {0:?}")]
+    SourceExists(String),
+
+    #[error("invalid source config: {0}")]
+    Config(String),
+}
+
+/// What came back instead of quotes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockKind {
+    /// No body, or only whitespace.
+    Empty,
+    /// An HTML page, e.g. a block page for the `Referer`.
+    Html,
+    /// A plain text notice to slow down.
+    RateLimited,
+    /// Records that don't decode in the declared charset.
+    Charset,
+    /// Anything else that isn't a `v_...` record.
+    Unrecognized,
+}
+
+impl fmt::Display for BlockKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BlockKind::Empty => "an empty body",
+            BlockKind::Html => "an html page",
+            BlockKind::RateLimited => "a rate limit notice",
+            BlockKind::Charset => "an undecodable body",
+            BlockKind::Unrecognized => "an unrecognized body",
+        })
+    }
+}
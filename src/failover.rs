@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use log::warn;
+
+use crate::{error::Result, realtime_data::QuoteOutcome, GainRTData};
+
+/// Asks an ordered list of sources in turn. Symbols the primary couldn't
+/// serve, because the request failed or the symbol came back missing or
+/// unparsable, are retried on the next source.
+///
+/// Each quote keeps the `source` of whoever served it.
+pub struct Failover {
+    sources: Vec<Box<dyn GainRTData>>,
+}
+
+impl Failover {
+    pub fn new(sources: Vec<Box<dyn GainRTData>>) -> Failover {
+        Failover { sources }
+    }
+}
+
+#[async_trait]
+impl GainRTData for Failover {
+    fn name(&self) -> &'static str {
+        "failover"
+    }
+
+    async fn quotes(
+        &self,
+        stocks_list: Vec<&str>,
+        prefix: bool,
+    ) -> Result<HashMap<String, QuoteOutcome>> {
+        let mut res = HashMap::new();
+        let mut pending = stocks_list;
+        let mut last_error = None;
+
+        for source in &self.sources {
+            if pending.is_empty() {
+                break;
+            }
+
+            let mut outcomes = match source.quotes(pending.clone(), prefix).await {
+                Ok(outcomes) => outcomes,
+                Err(e) => {
+                    warn!(
+                        "source {} failed, trying the next one: {}",
+                        source.name(),
+                        e
+                    );
+                    last_error = Some(e);
+                    continue;
+                }
+            };
+
+            let mut retry = vec![];
+            for symbol in pending {
+                match outcomes.remove(symbol) {
+                    Some(outcome @ QuoteOutcome::NotFound)
+                    | Some(outcome @ QuoteOutcome::ParseError { .. }) => {
+                        // keep it in case no later source does better
+                        res.insert(symbol.to_string(), outcome);
+                        retry.push(symbol);
+                    }
+                    Some(outcome) => {
+                        res.insert(symbol.to_string(), outcome);
+                    }
+                    None => retry.push(symbol),
+                }
+            }
+            pending = retry;
+        }
+
+        match last_error {
+            Some(e) if res.is_empty() => Err(e),
+            _ => {
+                for symbol in pending {
+                    res.entry(symbol.to_string())
+                        .or_insert(QuoteOutcome::NotFound);
+                }
+                Ok(res)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{error::RtError, testing::FakeSource};
+
+    fn served_by(outcome: Option<&QuoteOutcome>) -> Option<&str> {
+        match outcome {
+            Some(QuoteOutcome::Quote(rt_data)) => Some(&rt_data.source),
+            _ => None,
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_failover() {
+        let failover = Failover::new(vec![
+            FakeSource::new("down").failing().boxed(),
+            FakeSource::new("primary").with_codes(&["sh600036"]).boxed(),
+            FakeSource::new("secondary")
+                .with_codes(&["sh600036", "sz000001"])
+                .boxed(),
+        ]);
+
+        let res = failover
+            .quotes(vec!["sh600036", "sz000001", "sz000002"], true)
+            .await
+            .unwrap();
+        assert_eq!(served_by(res.get("sh600036")), Some("primary"));
+        assert_eq!(served_by(res.get("sz000001")), Some("secondary"));
+        assert!(matches!(res.get("sz000002"), Some(QuoteOutcome::NotFound)));
+    }
+
+    #[actix_web::test]
+    async fn test_failover_all_down() {
+        let failover = Failover::new(vec![
+            FakeSource::new("down").failing().boxed(),
+            FakeSource::new("also down").failing().boxed(),
+        ]);
+
+        match failover.quotes(vec!["sh600036"], true).await {
+            Err(RtError::Blocked { url, .. }) => assert_eq!(url, "also down"),
+            other => panic!("expected the last error, got {:?}", other),
+        }
+    }
+}
@@ -1,16 +1,118 @@
+pub mod capability;
+pub mod consensus;
+pub mod delta;
+pub mod error;
+pub mod failover;
+pub mod order_book;
+pub mod poller;
+pub mod price;
 pub mod realtime_data;
+pub mod registry;
 pub mod sina;
+pub mod status;
+pub mod subscription;
+pub mod symbol;
 pub mod tencent;
+#[cfg(test)]
+mod testing;
+pub mod units;
+
+use std::collections::{HashMap, HashSet};
 
 use async_trait::async_trait;
-use realtime_data::ItemData;
+use capability::{BarSource, Capability, FundamentalsSource, SearchSource, TickSource};
+use error::{Result, RtError};
+use realtime_data::{ItemData, QuoteOutcome};
 
 #[async_trait]
-pub trait GainRTData {
-    async fn stocks(&self, stocks_list: Vec<&str>, prefix: bool) -> Vec<ItemData>;
+pub trait GainRTData: Send + Sync {
+    /// Short name stamped on every quote this source serves.
+    fn name(&self) -> &'static str;
+
+    /// Fetches every symbol in `stocks_list`, keyed by the symbol exactly as
+    /// it was requested. Without `prefix` the symbols are bare codes and the
+    /// exchange is inferred from the code range.
+    async fn quotes(
+        &self,
+        stocks_list: Vec<&str>,
+        prefix: bool,
+    ) -> Result<HashMap<String, QuoteOutcome>>;
+
+    /// Like `quotes`, but fails on the first symbol that did not come back as
+    /// a quote. Results keep the request order.
+    async fn stocks(&self, stocks_list: Vec<&str>, prefix: bool) -> Result<Vec<ItemData>> {
+        let mut outcomes = self.quotes(stocks_list.clone(), prefix).await?;
+
+        let mut res = vec![];
+        for symbol in stocks_list {
+            match outcomes.remove(symbol) {
+                Some(QuoteOutcome::Quote(rt_data)) | Some(QuoteOutcome::Suspended(rt_data)) => {
+                    res.push(rt_data)
+                }
+                Some(QuoteOutcome::ParseError { details }) => return Err(RtError::Parse(details)),
+                Some(QuoteOutcome::NotFound) => {
+                    return Err(RtError::UnknownSymbol(symbol.to_string()))
+                }
+                Some(QuoteOutcome::Invalid { .. }) => {
+                    return Err(RtError::InvalidSymbol(symbol.to_string()))
+                }
+                Some(QuoteOutcome::Ambiguous { candidates }) => {
+                    return Err(RtError::AmbiguousSymbol {
+                        symbol: symbol.to_string(),
+                        candidates,
+                    })
+                }
+                // duplicated symbol, already taken
+                None => {}
+            }
+        }
+
+        Ok(res)
+    }
+
+    fn as_bars(&self) -> Option<&dyn BarSource> {
+        None
+    }
+
+    fn as_ticks(&self) -> Option<&dyn TickSource> {
+        None
+    }
+
+    fn as_search(&self) -> Option<&dyn SearchSource> {
+        None
+    }
+
+    fn as_fundamentals(&self) -> Option<&dyn FundamentalsSource> {
+        None
+    }
+
+    fn supports(&self, capability: Capability) -> bool {
+        match capability {
+            Capability::Quotes => true,
+            Capability::Bars => self.as_bars().is_some(),
+            Capability::Ticks => self.as_ticks().is_some(),
+            Capability::Search => self.as_search().is_some(),
+            Capability::Fundamentals => self.as_fundamentals().is_some(),
+        }
+    }
+
+    fn capabilities(&self) -> HashSet<Capability> {
+        [
+            Capability::Quotes,
+            Capability::Bars,
+            Capability::Ticks,
+            Capability::Search,
+            Capability::Fundamentals,
+        ]
+        .into_iter()
+        .filter(|c| self.supports(*c))
+        .collect()
+    }
 }
 
 // pub struct RtData {}
+/// The builtin sources. `registry::Registry` can create these and third party
+/// sources by name.
 #[derive(Clone, Copy, Debug)]
 pub enum RtData {
     Tencent,
@@ -0,0 +1,154 @@
+use serde::{Deserialize, Serialize};
+
+use crate::price::Decimal;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    Bid,
+    Ask,
+}
+
+/// One price level of the book; `volume` is in shares.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Level {
+    pub price:  Decimal,
+    pub volume: i64,
+}
+
+/// Price levels best first: bids descending, asks ascending. Vendors send a
+/// fixed number of levels; empty ones are left out, so either side may be
+/// shorter or empty.
+///
+/// The derived prices are `None` without the levels they need, or when the
+/// arithmetic on what came off the wire overflows.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OrderBook {
+    pub bids: Vec<Level>,
+    pub asks: Vec<Level>,
+}
+
+impl OrderBook {
+    pub fn side(&self, side: Side) -> &[Level] {
+        match side {
+            Side::Bid => &self.bids,
+            Side::Ask => &self.asks,
+        }
+    }
+
+    pub fn best_bid(&self) -> Option<Level> {
+        self.bids.first().copied()
+    }
+
+    pub fn best_ask(&self) -> Option<Level> {
+        self.asks.first().copied()
+    }
+
+    pub fn spread(&self) -> Option<Decimal> {
+        self.best_ask()?.price.checked_sub(self.best_bid()?.price)
+    }
+
+    pub fn mid_price(&self) -> Option<Decimal> {
+        let sum = self.best_ask()?.price.checked_add(self.best_bid()?.price)?;
+        sum.checked_div(Decimal::TWO)
+    }
+
+    /// The mid price leaned towards the side with less volume at the top, where
+    /// the next trade is more likely to move the price.
+    pub fn microprice(&self) -> Option<Decimal> {
+        let (bid, ask) = (self.best_bid()?, self.best_ask()?);
+        let volume = Decimal::from(bid.volume).checked_add(Decimal::from(ask.volume))?;
+        let price = bid
+            .price
+            .checked_mul(Decimal::from(ask.volume))?
+            .checked_add(ask.price.checked_mul(Decimal::from(bid.volume))?)?;
+        // `None` for no volume too
+        price.checked_div(volume)
+    }
+
+    /// Volume weighted price of the best `levels` levels of `side`.
+    pub fn depth_weighted_price(&self, side: Side, levels: usize) -> Option<Decimal> {
+        let levels = &self.side(side)[..levels.min(self.side(side).len())];
+        let (mut volume, mut amount) = (Decimal::ZERO, Decimal::ZERO);
+        for level in levels {
+            let shares = Decimal::from(level.volume);
+            volume = volume.checked_add(shares)?;
+            amount = amount.checked_add(level.price.checked_mul(shares)?)?;
+        }
+        amount.checked_div(volume)
+    }
+
+    /// The levels of `side` with the volume available at that price or better.
+    pub fn cumulative_depth(&self, side: Side) -> Vec<Level> {
+        self.side(side)
+            .iter()
+            .scan(0, |total, level| {
+                *total = level.volume.saturating_add(*total);
+                Some(Level {
+                    price:  level.price,
+                    volume: *total,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    fn level(price: Decimal, volume: i64) -> Level {
+        Level { price, volume }
+    }
+
+    #[test]
+    fn test_order_book() {
+        let book = OrderBook {
+            bids: vec![level(dec!(15.81), 50600), level(dec!(15.80), 143900)],
+            asks: vec![level(dec!(15.82), 34300), level(dec!(15.83), 266500)],
+        };
+
+        assert_eq!(book.spread(), Some(dec!(0.01)));
+        assert_eq!(book.mid_price(), Some(dec!(15.815)));
+        // more on the bid, so it leans towards the ask
+        let microprice = book.microprice().unwrap();
+        assert!(microprice > dec!(15.815) && microprice < dec!(15.82));
+        assert_eq!(book.depth_weighted_price(Side::Bid, 1), Some(dec!(15.81)));
+        let weighted = book.depth_weighted_price(Side::Ask, 5).unwrap();
+        assert!(weighted > dec!(15.82) && weighted < dec!(15.83));
+        assert_eq!(
+            book.cumulative_depth(Side::Bid),
+            vec![level(dec!(15.81), 50600), level(dec!(15.80), 194500)]
+        );
+
+        let empty = OrderBook::default();
+        assert_eq!(empty.spread(), None);
+        assert_eq!(empty.microprice(), None);
+        assert_eq!(empty.depth_weighted_price(Side::Ask, 5), None);
+        assert!(empty.cumulative_depth(Side::Ask).is_empty());
+
+        // garbage off the wire is `None` rather than a panic
+        let huge = OrderBook {
+            bids: vec![level(Decimal::MAX, i64::MAX), level(Decimal::MAX, i64::MAX)],
+            asks: vec![level(Decimal::MAX, i64::MAX)],
+        };
+        assert_eq!(huge.mid_price(), None);
+        assert_eq!(huge.microprice(), None);
+        assert_eq!(huge.depth_weighted_price(Side::Bid, 2), None);
+        let negative = OrderBook {
+            bids: vec![level(Decimal::MIN, 100)],
+            asks: vec![level(Decimal::MAX, 100)],
+        };
+        assert_eq!(negative.spread(), None);
+        let crossed = OrderBook {
+            bids: vec![level(dec!(15.81), 100)],
+            asks: vec![level(dec!(15.82), -100)],
+        };
+        assert_eq!(crossed.microprice(), None);
+        assert_eq!(
+            huge.cumulative_depth(Side::Bid)[1],
+            level(Decimal::MAX, i64::MAX)
+        );
+    }
+}
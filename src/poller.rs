@@ -0,0 +1,235 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use actix::{
+    dev::SendError, Actor, ActorFutureExt, AsyncContext, Context, Handler, Message, Recipient,
+    WrapFuture,
+};
+use log::warn;
+
+use crate::{
+    error::Result,
+    realtime_data::{ItemData, QuoteOutcome},
+    symbol::Symbol,
+    GainRTData, RtData,
+};
+
+/// A fresh quote for a symbol the recipient subscribed to.
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct QuoteUpdate {
+    pub symbol: Symbol,
+    pub quote:  ItemData,
+}
+
+/// Registers `recipient` for `symbol`. Subscribing twice needs two
+/// `Unsubscribe`s.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Subscribe {
+    pub symbol:    Symbol,
+    pub recipient: Recipient<QuoteUpdate>,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Unsubscribe {
+    pub symbol:    Symbol,
+    pub recipient: Recipient<QuoteUpdate>,
+}
+
+/// Polls a source on a fixed schedule and fans the quotes out to subscribed
+/// actors. Only the union of subscribed symbols is fetched, and a symbol is
+/// dropped from the poll once its last subscriber leaves.
+pub struct QuotePoller {
+    source:      Arc<dyn GainRTData>,
+    interval:    Duration,
+    subscribers: HashMap<Symbol, Vec<Recipient<QuoteUpdate>>>,
+    polling:     bool,
+}
+
+impl QuotePoller {
+    pub fn new(source: RtData, interval: Duration) -> QuotePoller {
+        QuotePoller::with_source(source.init(), interval)
+    }
+
+    pub fn with_source(source: Box<dyn GainRTData>, interval: Duration) -> QuotePoller {
+        QuotePoller {
+            source: Arc::from(source),
+            interval,
+            subscribers: HashMap::new(),
+            polling: false,
+        }
+    }
+
+    fn poll(&mut self, ctx: &mut Context<Self>) {
+        // a slow vendor must not stack requests up
+        if self.polling || self.subscribers.is_empty() {
+            return;
+        }
+        self.polling = true;
+
+        let source = self.source.clone();
+        let codes = self
+            .subscribers
+            .keys()
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>();
+        let fetch = async move {
+            source
+                .quotes(codes.iter().map(String::as_str).collect(), true)
+                .await
+        };
+
+        ctx.spawn(fetch.into_actor(self).map(|res, act, _| {
+            act.polling = false;
+            act.deliver(res);
+        }));
+    }
+
+    fn deliver(&mut self, res: Result<HashMap<String, QuoteOutcome>>) {
+        let mut outcomes = match res {
+            Ok(outcomes) => outcomes,
+            Err(e) => {
+                warn!("quote poller error: {}", e);
+                return;
+            }
+        };
+
+        for (symbol, recipients) in self.subscribers.iter_mut() {
+            let quote = match outcomes.remove(&symbol.to_string()) {
+                Some(QuoteOutcome::Quote(quote)) | Some(QuoteOutcome::Suspended(quote)) => quote,
+                outcome => {
+                    warn!("quote poller got no quote for {}: {:?}", symbol, outcome);
+                    continue;
+                }
+            };
+
+            recipients.retain(|recipient| {
+                let update = QuoteUpdate {
+                    symbol: symbol.clone(),
+                    quote:  quote.clone(),
+                };
+                // a stopped actor won't unsubscribe, forget it here
+                !matches!(recipient.try_send(update), Err(SendError::Closed(_)))
+            });
+        }
+
+        self.subscribers
+            .retain(|_, recipients| !recipients.is_empty());
+    }
+}
+
+impl Actor for QuotePoller {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Context<Self>) {
+        ctx.run_interval(self.interval, |act, ctx| act.poll(ctx));
+    }
+}
+
+impl Handler<Subscribe> for QuotePoller {
+    type Result = ();
+
+    fn handle(&mut self, msg: Subscribe, _: &mut Context<Self>) {
+        self.subscribers
+            .entry(msg.symbol)
+            .or_default()
+            .push(msg.recipient);
+    }
+}
+
+impl Handler<Unsubscribe> for QuotePoller {
+    type Result = ();
+
+    fn handle(&mut self, msg: Unsubscribe, _: &mut Context<Self>) {
+        if let Some(recipients) = self.subscribers.get_mut(&msg.symbol) {
+            if let Some(idx) = recipients.iter().position(|r| *r == msg.recipient) {
+                recipients.remove(idx);
+            }
+            if recipients.is_empty() {
+                self.subscribers.remove(&msg.symbol);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::testing::FakeSource;
+
+    struct Collector {
+        updates: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Actor for Collector {
+        type Context = Context<Self>;
+    }
+
+    impl Handler<QuoteUpdate> for Collector {
+        type Result = ();
+
+        fn handle(&mut self, msg: QuoteUpdate, _: &mut Context<Self>) {
+            self.updates.lock().unwrap().push(msg.quote.code);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_quote_poller() {
+        let source = FakeSource::new("recorder");
+        let requests = source.requests.clone();
+        let poller = QuotePoller::with_source(Box::new(source), Duration::from_millis(10)).start();
+
+        let sh = "sh600036".parse::<Symbol>().unwrap();
+        let sz = "sz000001".parse::<Symbol>().unwrap();
+        let a_updates = Arc::new(Mutex::new(vec![]));
+        let b_updates = Arc::new(Mutex::new(vec![]));
+        let a = Collector {
+            updates: a_updates.clone(),
+        }
+        .start()
+        .recipient();
+        let b = Collector {
+            updates: b_updates.clone(),
+        }
+        .start()
+        .recipient();
+
+        for (symbol, recipient) in [(&sh, &a), (&sh, &b), (&sz, &b)] {
+            poller
+                .send(Subscribe {
+                    symbol:    symbol.clone(),
+                    recipient: recipient.clone(),
+                })
+                .await
+                .unwrap();
+        }
+        actix::clock::sleep(Duration::from_millis(50)).await;
+
+        assert_eq!(
+            requests.lock().unwrap().last().unwrap(),
+            &vec!["sh600036".to_string(), "sz000001".to_string()]
+        );
+        assert!(a_updates.lock().unwrap().iter().all(|c| c == "sh600036"));
+        assert!(b_updates.lock().unwrap().contains(&"sz000001".to_string()));
+
+        // sh600036 is still wanted by b after a leaves
+        for (symbol, recipient) in [(&sh, &a), (&sz, &b)] {
+            poller
+                .send(Unsubscribe {
+                    symbol:    symbol.clone(),
+                    recipient: recipient.clone(),
+                })
+                .await
+                .unwrap();
+        }
+        actix::clock::sleep(Duration::from_millis(50)).await;
+
+        assert_eq!(
+            requests.lock().unwrap().last().unwrap(),
+            &vec!["sh600036".to_string()]
+        );
+    }
+}
@@ -0,0 +1,55 @@
+use rust_decimal::prelude::ToPrimitive;
+pub use rust_decimal::Decimal;
+
+/// Lossy float views of exact prices and amounts, for charting and statistics
+/// where a rounding error doesn't matter. Keep the `Decimal` for anything that
+/// has to reconcile to the cent.
+pub trait AsFloat {
+    fn as_f32(&self) -> f32;
+    fn as_f64(&self) -> f64;
+}
+
+impl AsFloat for Decimal {
+    fn as_f32(&self) -> f32 {
+        self.to_f32().unwrap_or_default()
+    }
+
+    fn as_f64(&self) -> f64 {
+        self.to_f64().unwrap_or_default()
+    }
+}
+
+/// The closest decimal to `value`, rounded to `scale` decimal places, e.g.
+/// `from_f64(15.81, 2)`. `None` for NaN and infinities.
+pub fn from_f64(value: f64, scale: u32) -> Option<Decimal> {
+    Decimal::try_from(value).ok().map(|d| d.round_dp(scale))
+}
+
+/// `price` as a whole number of `tick` sized steps, rounding to the nearest
+/// tick. `None` for a zero tick.
+pub fn to_ticks(price: Decimal, tick: Decimal) -> Option<i64> {
+    if tick.is_zero() {
+        return None;
+    }
+
+    (price / tick).round().to_i64()
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(dec!(15.81).as_f32(), 15.81);
+        assert_eq!(dec!(3068.08).as_f64(), 3068.08);
+        assert_eq!(from_f64(0.1 + 0.2, 3), Some(dec!(0.3)));
+        assert_eq!(from_f64(f64::NAN, 2), None);
+
+        assert_eq!(to_ticks(dec!(2.229), dec!(0.001)), Some(2229));
+        assert_eq!(to_ticks(dec!(15.81), dec!(0.01)), Some(1581));
+        assert_eq!(to_ticks(dec!(15.81), Decimal::ZERO), None);
+    }
+}
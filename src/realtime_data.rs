@@ -1,50 +1,428 @@
-use chrono::naive::NaiveDateTime;
+use std::collections::{BTreeMap, BTreeSet};
 
-#[derive(Default, Debug)]
+use chrono::{DateTime, Duration, FixedOffset, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    capability::Tick,
+    error::Result,
+    order_book::{Level, OrderBook},
+    price::Decimal,
+    status::{self, RiskFlag, TradingStatus},
+    symbol::{Exchange, InstrumentType, Symbol},
+};
+
+/// Version of the serialized `ItemData` shape, bumped whenever a field is
+/// added, removed, renamed or changes type or unit.
+pub const SCHEMA_VERSION: u32 = 9;
+
+/// JSON Schema of a serialized `QuoteMessage`, for consumers in other
+/// languages.
+pub const JSON_SCHEMA: &str = include_str!("../schema/quote.schema.json");
+
+/// A snapshot quote.
+///
+/// Serialized field names are the Rust field names, snake_case, and only change
+/// together with `SCHEMA_VERSION`. Timestamps are RFC 3339 with their UTC
+/// offset, e.g. `2022-04-19T16:14:03+08:00`, missing values are `null`. Prices
+/// and amounts are exact decimals, serialized as strings such as `"15.81"`;
+/// see `price::AsFloat` for float views.
+///
+/// Every quantity is in base units whatever the vendor sends: volumes in
+/// shares, or single bonds for bonds and repos, amounts and market values in
+/// units of `currency`, e.g. yuan. Sources convert lots, 万 and 亿 with the
+/// `units` helpers.
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
 pub struct ItemData {
+    /// The name as sent by the vendor, with the exchange's marks.
     pub name:                    String,
+    /// `name` without the marks in `risk_flags` and without padding.
+    pub display_name:            String,
     pub code:                    String,
-    pub now:                     f32,
-    pub close:                   f32,
-    pub open:                    f32,
+    pub now:                     Decimal,
+    pub close:                   Decimal,
+    pub open:                    Decimal,
+    /// Shares traded today.
+    pub volume:                  i64,
+    /// Shares traded at the ask, buyer initiated (外盘).
+    pub bid_volume:              i64,
+    /// Shares traded at the bid, seller initiated (内盘).
+    pub ask_volume:              i64,
+    pub book:                    OrderBook,
+    /// When the exchange stamped the quote, in the exchange's timezone.
+    pub datatime:                Option<DateTime<FixedOffset>>,
+    /// When this client finished reading the quote off the wire.
+    pub received_at:             Option<DateTime<Utc>>,
+    pub gain_amount:             Decimal,
+    pub gain_percentage:         f32,
+    pub high:                    Decimal,
+    pub low:                     Decimal,
+    /// Amount traded today.
+    pub total_value:             Decimal,
+    pub turnover:                Option<f32>,
+    /// Price over trailing twelve months earnings.
+    pub pe:                      Option<f32>,
+    pub pb:                      Option<f32>,
+    pub amplitude:               f32,
+    /// Free float market value.
+    pub traded_market_value:     Option<Decimal>,
+    pub market_value:            Option<Decimal>,
+    pub high_limit:              Decimal,
+    pub low_limit:               Decimal,
+    pub quantity_relative_ratio: Option<f32>,
+    /// Shares bid minus shares offered over the visible book (委差).
+    pub entrust_different:       Option<i64>,
+    pub average_price:           Option<Decimal>,
+    /// Price over forecast full-year earnings.
+    pub pe_dynamic:              Option<f32>,
+    /// Price over last reported full-year earnings.
+    pub pe_static:               Option<f32>,
+    pub high_52w:                Option<Decimal>,
+    pub low_52w:                 Option<Decimal>,
+    pub circulating_shares:      Option<i64>,
+    pub total_shares:            Option<i64>,
+    /// The vendor's own type field, e.g. `GP-A` for an A share.
+    pub vendor_type:             String,
+    /// From `vendor_type` when the source sends a known one, otherwise from
+    /// the code range.
+    pub instrument_type:         InstrumentType,
+    /// ISO 4217 code of prices and amounts, e.g. `CNY`.
+    pub currency:                String,
+    pub performance:             Performance,
+    /// Trades shortly before the quote, newest first, where the vendor sends
+    /// them.
+    pub recent_trades:           Vec<Tick>,
+    /// The vendor's fields that have no field of their own above, by their
+    /// index in the vendor's record. Blank fields are left out.
+    #[serde(with = "field_keys")]
+    pub vendor_fields:           BTreeMap<usize, FieldValue>,
+    /// Judged against `received_at`, a quote parsed on its own is `Trading`.
+    pub status:                  TradingStatus,
+    pub risk_flags:              BTreeSet<RiskFlag>,
+    /// Name of the source that served this quote, e.g. `tencent`.
+    pub source:                  String,
+}
+
+/// Percentage change of the price over trailing windows of trading days.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Performance {
+    pub change_5d:   Option<f32>,
+    pub change_10d:  Option<f32>,
+    pub change_20d:  Option<f32>,
+    pub change_60d:  Option<f32>,
+    pub change_120d: Option<f32>,
+}
+
+/// A vendor field without a meaning of its own, typed by how it reads.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldValue {
+    Integer(i64),
+    Decimal(Decimal),
+    Text(String),
+}
+
+impl FieldValue {
+    /// An integer if `text` reads as one, then a decimal, otherwise text.
+    pub fn infer(text: &str) -> FieldValue {
+        if let Ok(v) = text.parse() {
+            FieldValue::Integer(v)
+        } else if let Ok(v) = text.parse() {
+            FieldValue::Decimal(v)
+        } else {
+            FieldValue::Text(text.to_string())
+        }
+    }
+}
+
+/// `vendor_fields` with string keys, as JSON has them. Integer keys don't
+/// survive the buffering behind `QuoteMessage`'s flattening.
+mod field_keys {
+    use std::collections::BTreeMap;
+
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    use super::FieldValue;
+
+    pub fn serialize<S: Serializer>(
+        fields: &BTreeMap<usize, FieldValue>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(fields.iter().map(|(k, v)| (k.to_string(), v)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<BTreeMap<usize, FieldValue>, D::Error> {
+        BTreeMap::<String, FieldValue>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(k, v)| Ok((k.parse().map_err(D::Error::custom)?, v)))
+            .collect()
+    }
+}
+
+/// Levels of the book in an `ItemDataRef`.
+pub const BOOK_DEPTH: usize = 5;
+
+/// An `ItemData` borrowing its text from the response buffer, so a sweep over
+/// the whole market can parse without allocating. `into_owned` makes the
+/// `ItemData`, splitting the marks off the name on the way, and leaves the
+/// lists, `recent_trades` and `vendor_fields`, empty.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ItemDataRef<'a> {
+    pub name:                    &'a str,
+    pub code:                    &'a str,
+    pub now:                     Decimal,
+    pub close:                   Decimal,
+    pub open:                    Decimal,
     pub volume:                  i64,
     pub bid_volume:              i64,
     pub ask_volume:              i64,
-    pub bid1:                    f32,
-    pub bid1_volume:             i64,
-    pub bid2:                    f32,
-    pub bid2_volume:             i64,
-    pub bid3:                    f32,
-    pub bid3_volume:             i64,
-    pub bid4:                    f32,
-    pub bid4_volume:             i64,
-    pub bid5:                    f32,
-    pub bid5_volume:             i64,
-    pub ask1:                    f32,
-    pub ask1_volume:             i64,
-    pub ask2:                    f32,
-    pub ask2_volume:             i64,
-    pub ask3:                    f32,
-    pub ask3_volume:             i64,
-    pub ask4:                    f32,
-    pub ask4_volume:             i64,
-    pub ask5:                    f32,
-    pub ask5_volume:             i64,
-    pub datatime:                Option<NaiveDateTime>,
-    pub gain_amout:              f32,
+    /// Best first, empty levels have a zero price.
+    pub bids:                    [Level; BOOK_DEPTH],
+    pub asks:                    [Level; BOOK_DEPTH],
+    pub datatime:                Option<DateTime<FixedOffset>>,
+    pub gain_amount:             Decimal,
     pub gain_percentage:         f32,
-    pub high:                    f32,
-    pub low:                     f32,
-    pub total_value:             f64,
+    pub high:                    Decimal,
+    pub low:                     Decimal,
+    pub total_value:             Decimal,
     pub turnover:                Option<f32>,
     pub pe:                      Option<f32>,
     pub pb:                      Option<f32>,
     pub amplitude:               f32,
-    pub traded_market_value:     Option<f32>,
-    pub market_value:            Option<f32>,
-    pub high_limit:              f32,
-    pub low_limit:               f32,
+    pub traded_market_value:     Option<Decimal>,
+    pub market_value:            Option<Decimal>,
+    pub high_limit:              Decimal,
+    pub low_limit:               Decimal,
     pub quantity_relative_ratio: Option<f32>,
-    pub entrust_different:       Option<f32>,
-    pub average_price:           Option<f32>,
+    pub entrust_different:       Option<i64>,
+    pub average_price:           Option<Decimal>,
+    pub pe_dynamic:              Option<f32>,
+    pub pe_static:               Option<f32>,
+    pub high_52w:                Option<Decimal>,
+    pub low_52w:                 Option<Decimal>,
+    pub circulating_shares:      Option<i64>,
+    pub total_shares:            Option<i64>,
+    pub vendor_type:             &'a str,
+    pub instrument_type:         InstrumentType,
+    pub currency:                &'a str,
+    pub performance:             Performance,
+    pub status:                  TradingStatus,
+    pub source:                  &'static str,
+}
+
+impl ItemDataRef<'_> {
+    /// The bid levels that hold orders.
+    pub fn bids(&self) -> impl Iterator<Item = &Level> {
+        self.bids.iter().filter(|l| l.price > Decimal::ZERO)
+    }
+
+    /// The ask levels that hold orders.
+    pub fn asks(&self) -> impl Iterator<Item = &Level> {
+        self.asks.iter().filter(|l| l.price > Decimal::ZERO)
+    }
+
+    pub fn into_owned(self) -> ItemData {
+        let (display_name, risk_flags) = status::parse_name(self.name);
+
+        ItemData {
+            name: self.name.to_string(),
+            display_name,
+            code: self.code.to_string(),
+            now: self.now,
+            close: self.close,
+            open: self.open,
+            volume: self.volume,
+            bid_volume: self.bid_volume,
+            ask_volume: self.ask_volume,
+            book: OrderBook {
+                bids: self.bids().copied().collect(),
+                asks: self.asks().copied().collect(),
+            },
+            datatime: self.datatime,
+            received_at: None,
+            gain_amount: self.gain_amount,
+            gain_percentage: self.gain_percentage,
+            high: self.high,
+            low: self.low,
+            total_value: self.total_value,
+            turnover: self.turnover,
+            pe: self.pe,
+            pb: self.pb,
+            amplitude: self.amplitude,
+            traded_market_value: self.traded_market_value,
+            market_value: self.market_value,
+            high_limit: self.high_limit,
+            low_limit: self.low_limit,
+            quantity_relative_ratio: self.quantity_relative_ratio,
+            entrust_different: self.entrust_different,
+            average_price: self.average_price,
+            pe_dynamic: self.pe_dynamic,
+            pe_static: self.pe_static,
+            high_52w: self.high_52w,
+            low_52w: self.low_52w,
+            circulating_shares: self.circulating_shares,
+            total_shares: self.total_shares,
+            vendor_type: self.vendor_type.to_string(),
+            instrument_type: self.instrument_type,
+            currency: self.currency.to_string(),
+            performance: self.performance,
+            recent_trades: vec![],
+            vendor_fields: BTreeMap::new(),
+            status: self.status,
+            risk_flags,
+            source: self.source.to_string(),
+        }
+    }
+}
+
+impl From<ItemDataRef<'_>> for ItemData {
+    fn from(quote: ItemDataRef<'_>) -> Self {
+        quote.into_owned()
+    }
+}
+
+/// `ItemData` tagged with the schema version it was written with, the form to
+/// put on the wire.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct QuoteMessage {
+    pub schema_version: u32,
+    #[serde(flatten)]
+    pub quote:          ItemData,
+}
+
+impl From<ItemData> for QuoteMessage {
+    fn from(quote: ItemData) -> Self {
+        QuoteMessage {
+            schema_version: SCHEMA_VERSION,
+            quote,
+        }
+    }
+}
+
+impl ItemData {
+    pub fn symbol(&self) -> Result<Symbol> {
+        self.code.parse()
+    }
+
+    /// Time from the exchange stamp to receipt. Vendors stamp to the second.
+    /// During a session every listed code is restamped with each exchange
+    /// snapshot, traded or not, while a suspended code keeps the stamp of its
+    /// last session, so a large latency means either a stale vendor or a code
+    /// that isn't trading.
+    pub fn latency(&self) -> Option<Duration> {
+        Some(self.received_at? - self.datatime?.with_timezone(&Utc))
+    }
+}
+
+/// What a source returned for one requested symbol.
+#[derive(Debug)]
+pub enum QuoteOutcome {
+    Quote(ItemData),
+    NotFound,
+    Suspended(ItemData),
+    ParseError {
+        details: String,
+    },
+    /// Malformed, never sent to the vendor.
+    Invalid {
+        reason: String,
+    },
+    /// A bare code that more than one exchange could list.
+    Ambiguous {
+        candidates: Vec<Exchange>,
+    },
+}
+
+impl QuoteOutcome {
+    /// The quote carried by a `Quote` or `Suspended` outcome.
+    pub fn quote_mut(&mut self) -> Option<&mut ItemData> {
+        match self {
+            QuoteOutcome::Quote(rt_data) | QuoteOutcome::Suspended(rt_data) => Some(rt_data),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{naive::NaiveDateTime, TimeZone};
+    use rust_decimal_macros::dec;
+    use serde_json::Value;
+
+    use super::*;
+    use crate::symbol::Market;
+
+    #[test]
+    fn test_json_matches_schema() {
+        let local = NaiveDateTime::parse_from_str("20220419161403", "%Y%m%d%H%M%S").unwrap();
+        let quote = ItemData {
+            name: "平安银行".to_string(),
+            code: "sz000001".to_string(),
+            now: dec!(15.81),
+            volume: 82177200,
+            datatime: Market::China.localize(&local),
+            received_at: Some(Utc.ymd(2022, 4, 19).and_hms(8, 14, 5)),
+            gain_amount: dec!(-0.09),
+            pe: Some(8.44),
+            vendor_fields: BTreeMap::from([(57, FieldValue::Decimal(dec!(129422.6951)))]),
+            source: "tencent".to_string(),
+            ..Default::default()
+        };
+        let json = serde_json::to_value(QuoteMessage::from(quote)).unwrap();
+        let schema = serde_json::from_str::<Value>(JSON_SCHEMA).unwrap();
+
+        let fields = json.as_object().unwrap();
+        let properties = schema["properties"].as_object().unwrap();
+        let mut names = fields.keys().collect::<Vec<_>>();
+        let mut documented = properties.keys().collect::<Vec<_>>();
+        names.sort();
+        documented.sort();
+        assert_eq!(names, documented);
+
+        for (name, value) in fields {
+            let types = match &properties[name]["type"] {
+                Value::Array(types) => types.iter().map(|t| t.as_str().unwrap()).collect(),
+                t => vec![t.as_str().unwrap()],
+            };
+            let actual = match value {
+                Value::Null => "null",
+                Value::String(_) => "string",
+                Value::Number(n) if n.is_i64() => "integer",
+                Value::Number(_) => "number",
+                Value::Object(_) => "object",
+                Value::Array(_) => "array",
+                other => panic!("unexpected value {} for {}", other, name),
+            };
+            assert!(
+                types.contains(&actual) || (actual == "integer" && types.contains(&"number")),
+                "{} is {}, schema says {:?}",
+                name,
+                actual,
+                types
+            );
+        }
+
+        assert_eq!(json["schema_version"], SCHEMA_VERSION);
+        assert_eq!(
+            schema["properties"]["schema_version"]["const"],
+            SCHEMA_VERSION
+        );
+        assert_eq!(json["gain_amount"], "-0.09");
+        assert_eq!(json["datatime"], "2022-04-19T16:14:03+08:00");
+        assert_eq!(json["received_at"], "2022-04-19T08:14:05Z");
+        assert_eq!(json["pb"], Value::Null);
+        assert_eq!(json["vendor_fields"]["57"]["decimal"], "129422.6951");
+
+        let message = serde_json::from_value::<QuoteMessage>(json).unwrap();
+        assert_eq!(message.quote.name, "平安银行");
+        assert_eq!(message.quote.pe, Some(8.44));
+        assert_eq!(message.quote.now, dec!(15.81));
+        assert_eq!(
+            message.quote.vendor_fields[&57],
+            FieldValue::Decimal(dec!(129422.6951))
+        );
+        assert_eq!(message.quote.latency(), Some(Duration::seconds(2)));
+    }
 }
@@ -0,0 +1,161 @@
+use std::collections::{HashMap, HashSet};
+
+pub use crate::capability::Capability;
+use crate::{
+    error::{Result, RtError},
+    tencent::{ParseMode, Tencent},
+    GainRTData,
+};
+
+/// Free-form `key = value` settings handed to a source factory.
+pub type SourceConfig = HashMap<String, String>;
+
+pub type SourceFactory = Box<dyn Fn(&SourceConfig) -> Result<Box<dyn GainRTData>> + Send + Sync>;
+
+struct Registration {
+    capabilities: HashSet<Capability>,
+    factory:      SourceFactory,
+}
+
+/// Sources by name, so a service can pick one from a config string and third
+/// party crates can add their own without touching `RtData`.
+#[derive(Default)]
+pub struct Registry {
+    sources: HashMap<String, Registration>,
+}
+
+impl Registry {
+    /// An empty registry.
+    pub fn new() -> Registry {
+        Registry::default()
+    }
+
+    /// A registry with the sources built into this crate.
+    pub fn with_builtin() -> Registry {
+        let mut registry = Registry::new();
+        registry
+            .register(
+                Tencent::NAME,
+                &[Capability::Quotes, Capability::Fundamentals],
+                |config| {
+                    let mode = match config.get("mode") {
+                        Some(mode) => mode.parse()?,
+                        None => ParseMode::default(),
+                    };
+                    Ok(Box::new(Tencent::with_mode(mode)))
+                },
+            )
+            .expect("builtin source names are unique");
+
+        registry
+    }
+
+    pub fn register<F>(&mut self, name: &str, capabilities: &[Capability], factory: F) -> Result<()>
+    where
+        F: Fn(&SourceConfig) -> Result<Box<dyn GainRTData>> + Send + Sync + 'static,
+    {
+        if self.sources.contains_key(name) {
+            return Err(RtError::SourceExists(name.to_string()));
+        }
+
+        self.sources.insert(
+            name.to_string(),
+            Registration {
+                capabilities: capabilities.iter().copied().collect(),
+                factory:      Box::new(factory),
+            },
+        );
+
+        Ok(())
+    }
+
+    pub fn create(&self, name: &str, config: &SourceConfig) -> Result<Box<dyn GainRTData>> {
+        match self.sources.get(name) {
+            Some(registration) => (registration.factory)(config),
+            None => Err(RtError::UnknownSource(name.to_string())),
+        }
+    }
+
+    pub fn capabilities(&self, name: &str) -> Option<&HashSet<Capability>> {
+        self.sources.get(name).map(|r| &r.capabilities)
+    }
+
+    /// Registered names, sorted.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names = self.sources.keys().map(String::as_str).collect::<Vec<_>>();
+        names.sort_unstable();
+        names
+    }
+
+    /// Names of the sources that declared `capability`, sorted.
+    pub fn supporting(&self, capability: Capability) -> Vec<&str> {
+        let mut names = self
+            .sources
+            .iter()
+            .filter(|(_, r)| r.capabilities.contains(&capability))
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+        names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::failover::Failover;
+
+    #[test]
+    fn test_registry() {
+        let mut registry = Registry::with_builtin();
+        assert_eq!(registry.names(), vec!["tencent"]);
+
+        let source = registry.create("tencent", &SourceConfig::new()).unwrap();
+        assert_eq!(source.name(), "tencent");
+        let strict = SourceConfig::from([("mode".to_string(), "strict".to_string())]);
+        assert!(registry.create("tencent", &strict).is_ok());
+        let bogus = SourceConfig::from([("mode".to_string(), "loose".to_string())]);
+        assert!(matches!(
+            registry.create("tencent", &bogus),
+            Err(RtError::Config(_))
+        ));
+
+        registry
+            .register("backup", &[Capability::Quotes], |config| {
+                let primary = config
+                    .get("primary")
+                    .ok_or_else(|| RtError::Config("missing primary".to_string()))?;
+                if primary != "tencent" {
+                    return Err(RtError::Config(format!("unsupported primary {}", primary)));
+                }
+                Ok(Box::new(Failover::new(vec![Box::new(Tencent::new())])))
+            })
+            .unwrap();
+        assert_eq!(
+            registry.supporting(Capability::Quotes),
+            vec!["backup", "tencent"]
+        );
+        assert_eq!(
+            registry.supporting(Capability::Fundamentals),
+            vec!["tencent"]
+        );
+
+        let config = SourceConfig::from([("primary".to_string(), "tencent".to_string())]);
+        assert_eq!(
+            registry.create("backup", &config).unwrap().name(),
+            "failover"
+        );
+        assert!(matches!(
+            registry.create("backup", &SourceConfig::new()),
+            Err(RtError::Config(_))
+        ));
+        assert!(matches!(
+            registry.create("nope", &config),
+            Err(RtError::UnknownSource(_))
+        ));
+        assert!(matches!(
+            registry.register("tencent", &[], |_| Ok(Box::new(Tencent::new()))),
+            Err(RtError::SourceExists(_))
+        ));
+    }
+}
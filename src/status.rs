@@ -0,0 +1,150 @@
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TradingStatus {
+    #[default]
+    Trading,
+    /// Listed, but not trading today.
+    Suspended,
+}
+
+/// A mark the exchange puts on an A share's name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RiskFlag {
+    /// `ST`, special treatment after losses.
+    St,
+    /// `*ST`, special treatment with a delisting risk warning.
+    StarSt,
+    /// `退`, in the delisting period.
+    Delisting,
+    /// `N`, the first trading day.
+    NewListing,
+    /// `C`, the first days after listing, without a price limit.
+    NoPriceLimit,
+    /// `XR`, ex-rights today.
+    ExRights,
+    /// `XD`, ex-dividend today.
+    ExDividend,
+    /// `DR`, ex-rights and ex-dividend today.
+    ExRightsDividend,
+    /// `-U`, not yet profitable.
+    Unprofitable,
+    /// `-W`, weighted voting rights.
+    WeightedVoting,
+}
+
+/// Splits the exchange's marks off an A share name, e.g. `*ST海航` into
+/// `海航` and `StarSt`. Full-width letters and digits become ASCII and the
+/// padding between Chinese characters goes, so `万  科Ａ` displays as `万科A`.
+///
+/// Names without a Chinese character, such as HK and US names, are only
+/// normalized.
+pub fn parse_name(name: &str) -> (String, BTreeSet<RiskFlag>) {
+    let mut flags = BTreeSet::new();
+    let mut rest = normalize(name);
+    if rest.is_ascii() {
+        return (rest, flags);
+    }
+
+    // marks only count when a Chinese character follows, `TCL科技` is a name
+    let prefixes = [
+        ("*ST", RiskFlag::StarSt),
+        ("ST", RiskFlag::St),
+        ("XR", RiskFlag::ExRights),
+        ("XD", RiskFlag::ExDividend),
+        ("DR", RiskFlag::ExRightsDividend),
+        ("N", RiskFlag::NewListing),
+        ("C", RiskFlag::NoPriceLimit),
+    ];
+    while let Some((stripped, flag)) = prefixes.iter().find_map(|(prefix, flag)| {
+        rest.strip_prefix(prefix)
+            .filter(|r| r.chars().next().is_some_and(|c| !c.is_ascii()))
+            .map(|r| (r.to_string(), *flag))
+    }) {
+        flags.insert(flag);
+        rest = stripped;
+    }
+
+    if let Some(stripped) = rest.strip_prefix("退市") {
+        flags.insert(RiskFlag::Delisting);
+        rest = stripped.to_string();
+    } else if let Some(stripped) = rest.strip_suffix('退') {
+        flags.insert(RiskFlag::Delisting);
+        rest = stripped.to_string();
+    }
+
+    // `-U`, `-W` or `-UW` after the name
+    if let Some((head, marks)) = rest.rsplit_once('-') {
+        if !marks.is_empty() && marks.chars().all(|c| c == 'U' || c == 'W') {
+            flags.extend(marks.chars().map(|c| match c {
+                'U' => RiskFlag::Unprofitable,
+                _ => RiskFlag::WeightedVoting,
+            }));
+            rest = head.to_string();
+        }
+    }
+
+    (rest, flags)
+}
+
+/// Full-width ASCII to ASCII, trimmed and without the padding between Chinese
+/// characters. Spaces between words, as in `BERKSHIRE HATHAWAY`, stay.
+fn normalize(name: &str) -> String {
+    let chars = name
+        .trim()
+        .chars()
+        .map(|c| match c {
+            '\u{ff01}'..='\u{ff5e}' => char::from_u32(c as u32 - 0xfee0).unwrap_or(c),
+            c => c,
+        })
+        .collect::<Vec<_>>();
+
+    let mut normalized = String::with_capacity(name.len());
+    for (i, &c) in chars.iter().enumerate() {
+        if c.is_whitespace() {
+            let before = normalized.chars().next_back();
+            let after = chars[i + 1..].iter().find(|c| !c.is_whitespace());
+            if before.is_some_and(|c| !c.is_ascii()) && after.is_some_and(|c| !c.is_ascii()) {
+                continue;
+            }
+        }
+        normalized.push(c);
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_name() {
+        use RiskFlag::{WeightedVoting as Weighted, *};
+
+        for (name, display, flags) in [
+            ("平安银行", "平安银行", vec![]),
+            ("万  科Ａ", "万科A", vec![]),
+            ("*ST海航", "海航", vec![StarSt]),
+            ("ST康美", "康美", vec![St]),
+            ("退市海润", "海润", vec![Delisting]),
+            ("长生退", "长生", vec![Delisting]),
+            ("N华康", "华康", vec![NewListing]),
+            ("C华康", "华康", vec![NoPriceLimit]),
+            ("XD平安银", "平安银", vec![ExDividend]),
+            ("DR中国平", "中国平", vec![ExRightsDividend]),
+            ("N云从-UW", "云从", vec![NewListing, Unprofitable, Weighted]),
+            ("TCL科技", "TCL科技", vec![]),
+            ("TENCENT", "TENCENT", vec![]),
+            ("BERKSHIRE HATHAWAY", "BERKSHIRE HATHAWAY", vec![]),
+            (" ＴＣＬ 科技\u{3000}", "TCL 科技", vec![]),
+        ] {
+            let (parsed, parsed_flags) = parse_name(name);
+            assert_eq!(parsed, display, "{}", name);
+            assert_eq!(parsed_flags, flags.into_iter().collect(), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use std::{
+    collections::HashSet,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures_util::Stream;
+use log::warn;
+use tokio::{
+    sync::mpsc,
+    task::JoinHandle,
+    time::{self, MissedTickBehavior},
+};
+
+use crate::{
+    realtime_data::{ItemData, QuoteOutcome},
+    symbol::Symbol,
+    GainRTData,
+};
+
+/// Quotes buffered between the poller and a slow consumer. Once full the
+/// poller waits, so a consumer that falls behind slows the polling down
+/// instead of piling up stale snapshots.
+const BUFFER_SIZE: usize = 256;
+
+/// A stream of quote updates polled from a source at a fixed interval.
+///
+/// Symbols can be added and removed while the stream runs. Polling stops when
+/// the subscription is dropped.
+pub struct Subscription {
+    receiver: mpsc::Receiver<ItemData>,
+    symbols:  Arc<Mutex<HashSet<Symbol>>>,
+    handle:   JoinHandle<()>,
+}
+
+/// Polls `source` for `symbols` every `interval`. Must be called from within a
+/// tokio (or actix) runtime.
+///
+/// Ticks missed while a request or a slow consumer held the poller up are
+/// skipped rather than fired in a burst.
+pub fn subscribe(
+    source: Arc<dyn GainRTData>,
+    symbols: Vec<Symbol>,
+    interval: Duration,
+) -> Subscription {
+    let (sender, receiver) = mpsc::channel(BUFFER_SIZE);
+    let symbols = Arc::new(Mutex::new(symbols.into_iter().collect::<HashSet<_>>()));
+
+    let handle = tokio::spawn(poll(source, symbols.clone(), interval, sender));
+
+    Subscription {
+        receiver,
+        symbols,
+        handle,
+    }
+}
+
+async fn poll(
+    source: Arc<dyn GainRTData>,
+    symbols: Arc<Mutex<HashSet<Symbol>>>,
+    interval: Duration,
+    sender: mpsc::Sender<ItemData>,
+) {
+    let mut ticker = time::interval(interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+    loop {
+        ticker.tick().await;
+
+        let codes = symbols
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>();
+        if codes.is_empty() {
+            continue;
+        }
+
+        let outcomes = match source
+            .quotes(codes.iter().map(String::as_str).collect(), true)
+            .await
+        {
+            Ok(outcomes) => outcomes,
+            Err(e) => {
+                warn!("subscription poll error: {}", e);
+                continue;
+            }
+        };
+
+        for (symbol, outcome) in outcomes {
+            let rt_data = match outcome {
+                QuoteOutcome::Quote(rt_data) | QuoteOutcome::Suspended(rt_data) => rt_data,
+                outcome => {
+                    warn!("subscription got no quote for {}: {:?}", symbol, outcome);
+                    continue;
+                }
+            };
+
+            if sender.send(rt_data).await.is_err() {
+                // the subscription was dropped
+                return;
+            }
+        }
+    }
+}
+
+impl Subscription {
+    /// Starts polling `symbol` from the next tick on.
+    pub fn add(&self, symbol: Symbol) {
+        self.symbols.lock().unwrap().insert(symbol);
+    }
+
+    /// Stops polling `symbol`. Updates already buffered are still delivered.
+    pub fn remove(&self, symbol: &Symbol) {
+        self.symbols.lock().unwrap().remove(symbol);
+    }
+
+    pub fn symbols(&self) -> Vec<Symbol> {
+        self.symbols.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+impl Stream for Subscription {
+    type Item = ItemData;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<ItemData>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use futures_util::StreamExt;
+
+    use super::*;
+    use crate::testing::FakeSource;
+
+    #[actix_web::test]
+    async fn test_subscribe() {
+        let source = Arc::new(FakeSource::new("counter").counting());
+        let sh = "sh600036".parse::<Symbol>().unwrap();
+        let sz = "sz000001".parse::<Symbol>().unwrap();
+
+        let mut sub = subscribe(source.clone(), vec![sh.clone()], Duration::from_millis(5));
+
+        let first = sub.next().await.unwrap();
+        assert_eq!(first.code, "sh600036");
+        let second = sub.next().await.unwrap();
+        assert!(second.volume > first.volume);
+
+        sub.add(sz.clone());
+        sub.remove(&sh);
+        loop {
+            let rt_data = sub.next().await.unwrap();
+            if rt_data.code == "sz000001" {
+                break;
+            }
+        }
+        assert_eq!(sub.symbols(), vec![sz]);
+
+        drop(sub);
+        time::sleep(Duration::from_millis(20)).await;
+        let calls = source.calls.load(Ordering::SeqCst);
+        time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(source.calls.load(Ordering::SeqCst), calls);
+    }
+}
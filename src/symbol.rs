@@ -0,0 +1,493 @@
+use std::{cell::Cell, fmt, str::FromStr};
+
+use chrono::{
+    naive::{NaiveDate, NaiveDateTime},
+    DateTime, FixedOffset, Offset, TimeZone, Timelike,
+};
+use chrono_tz::{America, Asia, Tz};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, RtError};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Exchange {
+    Sse,
+    Szse,
+    Bse,
+    Hkex,
+    Us,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Market {
+    China,
+    HongKong,
+    UnitedStates,
+}
+
+/// What an instrument is.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InstrumentType {
+    Stock,
+    Etf,
+    Lof,
+    /// Any other fund, e.g. a closed-end or graded fund.
+    Fund,
+    Reit,
+    Index,
+    Bond,
+    ConvertibleBond,
+    Repo,
+    #[default]
+    Unknown,
+}
+
+/// How a vendor spells a symbol on the wire.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SymbolStyle {
+    /// `sh600036`, `hk00700`, `usAAPL`
+    Tencent,
+    /// `1.600036`, `116.00700`, US listings only with their venue
+    EastMoney,
+    /// `600036.SS`, `0700.HK`, `AAPL`, `BRK-B`
+    Yahoo,
+}
+
+/// A validated instrument code on a known exchange.
+///
+/// Parses from and displays as the prefixed form, e.g. `sh600036`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Symbol {
+    exchange:        Exchange,
+    code:            String,
+    instrument_type: InstrumentType,
+}
+
+/// The market and local minute `Market::localize` caches an offset for.
+type OffsetKey = (Market, NaiveDate, u32, u32);
+
+/// ISO 4217 codes of the currencies vendors quote in, for telling a currency
+/// field from other three letter fields such as `ETF`.
+pub const CURRENCIES: [&str; 8] = ["CNY", "HKD", "USD", "EUR", "GBP", "JPY", "SGD", "AUD"];
+
+impl Exchange {
+    /// The market prefix used by the quote vendors, e.g. `sh600036`.
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            Exchange::Sse => "sh",
+            Exchange::Szse => "sz",
+            Exchange::Bse => "bj",
+            Exchange::Hkex => "hk",
+            Exchange::Us => "us",
+        }
+    }
+
+    /// The exchange a vendor `prefix` stands for, e.g. `sh`.
+    pub fn from_prefix(prefix: &str) -> Option<Exchange> {
+        match prefix {
+            "sh" => Some(Exchange::Sse),
+            "sz" => Some(Exchange::Szse),
+            "bj" => Some(Exchange::Bse),
+            "hk" => Some(Exchange::Hkex),
+            "us" => Some(Exchange::Us),
+            _ => None,
+        }
+    }
+
+    pub fn market(&self) -> Market {
+        match self {
+            Exchange::Sse | Exchange::Szse | Exchange::Bse => Market::China,
+            Exchange::Hkex => Market::HongKong,
+            Exchange::Us => Market::UnitedStates,
+        }
+    }
+
+    /// Exchanges whose code ranges cover a bare six digit `code`.
+    ///
+    /// An empty result means the code is malformed or in no known range, more
+    /// than one means the code alone can't tell the exchanges apart. `000xxx`
+    /// is resolved to SZSE; SSE indices in that range need an explicit `sh`.
+    pub fn candidates(code: &str) -> Vec<Exchange> {
+        if !is_a_share_code(code) {
+            return vec![];
+        }
+
+        [Exchange::Sse, Exchange::Szse, Exchange::Bse]
+            .into_iter()
+            .filter(|exchange| match (exchange, &code[..3]) {
+                (Exchange::Sse, "000") => false,
+                (exchange, _) => InstrumentType::from_code(*exchange, code).is_some(),
+            })
+            .collect()
+    }
+}
+
+impl Market {
+    /// The timezone the market's exchanges stamp their quotes in.
+    pub fn timezone(&self) -> Tz {
+        match self {
+            Market::China => Asia::Shanghai,
+            Market::HongKong => Asia::Hong_Kong,
+            Market::UnitedStates => America::New_York,
+        }
+    }
+
+    /// ISO 4217 code of the currency the market trades in.
+    pub fn currency(&self) -> &'static str {
+        match self {
+            Market::China => "CNY",
+            Market::HongKong => "HKD",
+            Market::UnitedStates => "USD",
+        }
+    }
+
+    /// Attaches the market's UTC offset to an exchange local time. A time
+    /// repeated by a DST change resolves to the earlier instant, one skipped
+    /// by it to `None`.
+    pub fn localize(&self, local: &NaiveDateTime) -> Option<DateTime<FixedOffset>> {
+        // offsets change on a whole minute, and a sweep stamps most quotes in
+        // the same one, so the last lookup is kept
+        thread_local! {
+            static LAST: Cell<Option<(OffsetKey, Option<FixedOffset>)>> =
+                const { Cell::new(None) };
+        }
+
+        let key = (*self, local.date(), local.hour(), local.minute());
+        let offset = match LAST.with(Cell::get) {
+            Some((last, offset)) if last == key => offset,
+            _ => {
+                let offset = self
+                    .timezone()
+                    .from_local_datetime(local)
+                    .earliest()
+                    .map(|dt| dt.offset().fix());
+                LAST.with(|last| last.set(Some((key, offset))));
+                offset
+            }
+        };
+        offset?.from_local_datetime(local).single()
+    }
+}
+
+impl InstrumentType {
+    /// Maps Tencent's type field, e.g. `GP-A`. `None` for a type it doesn't
+    /// know, callers fall back to `from_code`.
+    pub fn from_vendor_type(kind: &str) -> Option<InstrumentType> {
+        use InstrumentType::*;
+
+        let kind = kind.trim();
+        if kind.get(..2).is_some_and(|p| p.eq_ignore_ascii_case("GP")) {
+            return Some(Stock);
+        }
+
+        [
+            ("ETF", Etf),
+            ("LOF", Lof),
+            ("JJ", Fund),
+            ("FJ", Fund),
+            ("REIT", Reit),
+            ("REITS", Reit),
+            ("ZS", Index),
+            ("ZQ", Bond),
+            ("KZZ", ConvertibleBond),
+            ("HG", Repo),
+            ("ZQHG", Repo),
+        ]
+        .into_iter()
+        .find(|(name, _)| kind.eq_ignore_ascii_case(name))
+        .map(|(_, kind)| kind)
+    }
+
+    /// Classifies a code by the exchange's code-range rules. `None` when the
+    /// exchange doesn't number anything in that range.
+    pub fn from_code(exchange: Exchange, code: &str) -> Option<InstrumentType> {
+        use InstrumentType::*;
+
+        if exchange.market() == Market::China && !is_a_share_code(code) {
+            return None;
+        }
+
+        match exchange {
+            Exchange::Sse => match &code[..3] {
+                "600" | "601" | "603" | "605" | "688" | "689" | "900" => Some(Stock),
+                p if ("510".."519").contains(&p) => Some(Etf),
+                "560" | "561" | "562" | "563" | "588" | "589" => Some(Etf),
+                "501" | "502" | "506" => Some(Lof),
+                "508" => Some(Reit),
+                p if ("500".."590").contains(&p) => Some(Fund),
+                "000" => Some(Index),
+                "204" => Some(Repo),
+                "110" | "111" | "113" | "118" => Some(ConvertibleBond),
+                p if matches!(&p[..2], "01" | "10" | "11") => Some(Bond),
+                _ => None,
+            },
+            Exchange::Szse => match &code[..3] {
+                "000" | "001" | "002" | "003" | "004" | "300" | "301" | "200" => Some(Stock),
+                "159" => Some(Etf),
+                p if ("160".."170").contains(&p) => Some(Lof),
+                "180" => Some(Reit),
+                p if ("150".."190").contains(&p) => Some(Fund),
+                "399" => Some(Index),
+                "131" => Some(Repo),
+                "123" | "127" | "128" => Some(ConvertibleBond),
+                p if matches!(&p[..2], "10" | "12") => Some(Bond),
+                _ => None,
+            },
+            // NEEQ-era and current Beijing codes
+            Exchange::Bse => match &code[..3] {
+                "920" => Some(Stock),
+                p if matches!(&p[..2], "43" | "83" | "87" | "88") => Some(Stock),
+                _ => None,
+            },
+            Exchange::Hkex | Exchange::Us => Some(Stock),
+        }
+    }
+}
+
+impl Symbol {
+    pub fn new(exchange: Exchange, code: &str) -> Result<Symbol> {
+        let valid = match exchange.market() {
+            Market::China => true,
+            Market::HongKong => code.len() == 5 && code.bytes().all(|c| c.is_ascii_digit()),
+            Market::UnitedStates => {
+                (1..=10).contains(&code.len())
+                    && code.starts_with(|c: char| c.is_ascii_uppercase())
+                    && code
+                        .bytes()
+                        .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == b'.')
+            }
+        };
+
+        match InstrumentType::from_code(exchange, code) {
+            Some(instrument_type) if valid => Ok(Symbol {
+                exchange,
+                code: code.to_string(),
+                instrument_type,
+            }),
+            _ => Err(RtError::InvalidSymbol(format!(
+                "{}{}",
+                exchange.prefix(),
+                code
+            ))),
+        }
+    }
+
+    /// Resolves a bare mainland code such as `600036` to its exchange.
+    pub fn from_code(code: &str) -> Result<Symbol> {
+        match Exchange::candidates(code).as_slice() {
+            [exchange] => Symbol::new(*exchange, code),
+            [] => Err(RtError::InvalidSymbol(code.to_string())),
+            candidates => Err(RtError::AmbiguousSymbol {
+                symbol:     code.to_string(),
+                candidates: candidates.to_vec(),
+            }),
+        }
+    }
+
+    pub fn exchange(&self) -> Exchange {
+        self.exchange
+    }
+
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    /// The instrument type implied by the code range.
+    pub fn instrument_type(&self) -> InstrumentType {
+        self.instrument_type
+    }
+
+    /// `None` when `style` can't spell the symbol: EastMoney numbers US
+    /// listings by venue, 105 for NASDAQ and 106 for NYSE, and a `us` code
+    /// doesn't say which.
+    pub fn render(&self, style: SymbolStyle) -> Option<String> {
+        Some(match style {
+            SymbolStyle::Tencent => self.to_string(),
+            SymbolStyle::EastMoney => {
+                let market = match self.exchange {
+                    Exchange::Sse => "1",
+                    Exchange::Szse | Exchange::Bse => "0",
+                    Exchange::Hkex => "116",
+                    Exchange::Us => return None,
+                };
+                format!("{}.{}", market, self.code)
+            }
+            SymbolStyle::Yahoo => match self.exchange {
+                Exchange::Sse => format!("{}.SS", self.code),
+                Exchange::Szse => format!("{}.SZ", self.code),
+                Exchange::Bse => format!("{}.BJ", self.code),
+                Exchange::Hkex => format!("{}.HK", &self.code[1..]),
+                // share classes take a dash, `BRK-B`
+                Exchange::Us => self.code.replace('.', "-"),
+            },
+        })
+    }
+}
+
+impl FromStr for Symbol {
+    type Err = RtError;
+
+    fn from_str(s: &str) -> Result<Symbol> {
+        let exchange = match s.get(..2).and_then(Exchange::from_prefix) {
+            Some(exchange) => exchange,
+            None => return Err(RtError::InvalidSymbol(s.to_string())),
+        };
+
+        Symbol::new(exchange, &s[2..])
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.exchange.prefix(), self.code)
+    }
+}
+
+fn is_a_share_code(code: &str) -> bool {
+    code.len() == 6 && code.bytes().all(|c| c.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_candidates() {
+        assert_eq!(Exchange::candidates("600036"), vec![Exchange::Sse]);
+        assert_eq!(Exchange::candidates("000001"), vec![Exchange::Szse]);
+        assert_eq!(Exchange::candidates("159928"), vec![Exchange::Szse]);
+        assert_eq!(Exchange::candidates("430047"), vec![Exchange::Bse]);
+        assert_eq!(Exchange::candidates("830799"), vec![Exchange::Bse]);
+        assert_eq!(Exchange::candidates("518801"), vec![Exchange::Sse]);
+        assert_eq!(
+            Exchange::candidates("101901"),
+            vec![Exchange::Sse, Exchange::Szse]
+        );
+        assert!(Exchange::candidates("60003").is_empty());
+        assert!(Exchange::candidates("sh600036").is_empty());
+        assert!(Exchange::candidates("700000").is_empty());
+    }
+
+    #[test]
+    fn test_symbol_from_str() {
+        let symbol = "sh600036".parse::<Symbol>().unwrap();
+        assert_eq!(symbol.exchange(), Exchange::Sse);
+        assert_eq!(symbol.code(), "600036");
+        assert_eq!(symbol.instrument_type(), InstrumentType::Stock);
+        assert_eq!(symbol.to_string(), "sh600036");
+
+        let index = "sh000001".parse::<Symbol>().unwrap();
+        assert_eq!(index.instrument_type(), InstrumentType::Index);
+        assert_eq!(Symbol::from_code("000001").unwrap().to_string(), "sz000001");
+
+        assert_eq!(
+            "hk00700".parse::<Symbol>().unwrap().exchange(),
+            Exchange::Hkex
+        );
+        assert_eq!("usBRK.B".parse::<Symbol>().unwrap().code(), "BRK.B");
+
+        for s in [
+            "SH600036",
+            "sh600036 ",
+            "sh60003",
+            "sz600036",
+            "hk700",
+            "usaapl",
+            "",
+        ] {
+            assert!(s.parse::<Symbol>().is_err(), "{} should not parse", s);
+        }
+    }
+
+    #[test]
+    fn test_instrument_type() {
+        use InstrumentType::*;
+
+        assert_eq!(InstrumentType::from_vendor_type("GP-A"), Some(Stock));
+        assert_eq!(InstrumentType::from_vendor_type("GP-A-KCB"), Some(Stock));
+        assert_eq!(InstrumentType::from_vendor_type("etf"), Some(Etf));
+        assert_eq!(
+            InstrumentType::from_vendor_type("KZZ"),
+            Some(ConvertibleBond)
+        );
+        assert_eq!(InstrumentType::from_vendor_type(""), None);
+
+        for (exchange, code, expected) in [
+            (Exchange::Sse, "600036", Some(Stock)),
+            (Exchange::Sse, "518801", Some(Etf)),
+            (Exchange::Sse, "501018", Some(Lof)),
+            (Exchange::Sse, "508000", Some(Reit)),
+            (Exchange::Sse, "113050", Some(ConvertibleBond)),
+            (Exchange::Sse, "019547", Some(Bond)),
+            (Exchange::Sse, "000300", Some(Index)),
+            (Exchange::Sse, "204001", Some(Repo)),
+            (Exchange::Szse, "159928", Some(Etf)),
+            (Exchange::Szse, "161725", Some(Lof)),
+            (Exchange::Szse, "180201", Some(Reit)),
+            (Exchange::Szse, "128136", Some(ConvertibleBond)),
+            (Exchange::Szse, "399001", Some(Index)),
+            (Exchange::Szse, "700000", None),
+        ] {
+            assert_eq!(
+                InstrumentType::from_code(exchange, code),
+                expected,
+                "{}",
+                code
+            );
+        }
+    }
+
+    #[test]
+    fn test_localize() {
+        let local = NaiveDateTime::parse_from_str("20220419161403", "%Y%m%d%H%M%S").unwrap();
+        let sh = Market::China.localize(&local).unwrap();
+        assert_eq!(sh.to_rfc3339(), "2022-04-19T16:14:03+08:00");
+        // New York is on daylight saving time in April
+        let us = Market::UnitedStates.localize(&local).unwrap();
+        assert_eq!(us.to_rfc3339(), "2022-04-19T16:14:03-04:00");
+        assert!(sh < us);
+
+        // clocks jumped from 02:00 to 03:00
+        let skipped = NaiveDateTime::parse_from_str("20220313023000", "%Y%m%d%H%M%S").unwrap();
+        assert_eq!(Market::UnitedStates.localize(&skipped), None);
+        assert_eq!(
+            Market::UnitedStates.localize(&(skipped + Duration::seconds(59))),
+            None
+        );
+        // and back from 02:00 to 01:00, the earlier of the repeated times
+        // wins, also once the offset is cached
+        let repeated = NaiveDateTime::parse_from_str("20221106013000", "%Y%m%d%H%M%S").unwrap();
+        for local in [repeated, repeated + Duration::seconds(1)] {
+            let us = Market::UnitedStates.localize(&local).unwrap();
+            assert_eq!(us.offset().local_minus_utc(), -4 * 3600);
+        }
+        let later = repeated + Duration::hours(1);
+        let us = Market::UnitedStates.localize(&later).unwrap();
+        assert_eq!(us.to_rfc3339(), "2022-11-06T02:30:00-05:00");
+    }
+
+    #[test]
+    fn test_symbol_render() {
+        use SymbolStyle::*;
+
+        for (symbol, style, rendered) in [
+            ("sh600036", Tencent, Some("sh600036")),
+            ("sh600036", EastMoney, Some("1.600036")),
+            ("sh600036", Yahoo, Some("600036.SS")),
+            ("sz159928", EastMoney, Some("0.159928")),
+            ("sz159928", Yahoo, Some("159928.SZ")),
+            ("hk00700", EastMoney, Some("116.00700")),
+            ("hk00700", Yahoo, Some("0700.HK")),
+            ("usBRK.B", Tencent, Some("usBRK.B")),
+            // NYSE, but nothing in the code says so
+            ("usBRK.B", EastMoney, None),
+            ("usBRK.B", Yahoo, Some("BRK-B")),
+        ] {
+            let symbol = symbol.parse::<Symbol>().unwrap();
+            assert_eq!(symbol.render(style).as_deref(), rendered, "{}", symbol);
+        }
+    }
+}
@@ -1,31 +1,136 @@
-use anyhow::Result;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
 use async_trait::async_trait;
-use chrono::naive::NaiveDateTime;
-use log::{info, warn};
-use nom::{
-    self,
-    bytes::complete::{is_not, tag, take_while},
-    IResult,
+use chrono::{
+    naive::{NaiveDate, NaiveDateTime, NaiveTime},
+    DateTime, Utc,
+};
+use encoding_rs::{Encoding, UTF_8};
+use futures_util::{stream, Stream, TryStreamExt};
+use reqwest::{
+    header::{HeaderMap, CONTENT_TYPE},
+    Response, StatusCode,
+};
+
+use crate::{
+    capability::{Fundamentals, FundamentalsSource, Tick, TickSide},
+    error::{BlockKind, Result, RtError},
+    order_book::Level,
+    price::Decimal,
+    realtime_data::{FieldValue, ItemData, ItemDataRef, Performance, QuoteOutcome},
+    symbol::{Exchange, InstrumentType, Market, Symbol, CURRENCIES},
+    units, GainRTData,
 };
-use reqwest::{header::HeaderMap, Response};
 
-use crate::{realtime_data::ItemData, GainRTData};
+use self::{diagnostics::Checker, response::Sniffed};
+pub use self::{
+    diagnostics::{Diagnostic, ParseMode, WarnLimiter, WARN_INTERVAL},
+    grammar::{field_name, FromField, Record, RecordError, RecordErrorKind},
+    response::ResponseParser,
+};
+
+mod diagnostics;
+mod grammar;
+mod response;
 
 pub struct Tencent {
     headers:   reqwest::header::HeaderMap,
     stock_api: &'static str,
+    mode:      ParseMode,
 }
 
 #[async_trait]
 impl GainRTData for Tencent {
-    async fn stocks(&self, stocks_list: Vec<&str>, prefix: bool) -> Vec<ItemData> {
-        let resp = self.get_stocks(stocks_list).await.unwrap();
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    async fn quotes(
+        &self,
+        stocks_list: Vec<&str>,
+        prefix: bool,
+    ) -> Result<HashMap<String, QuoteOutcome>> {
+        let mut res = HashMap::new();
+
+        // (requested symbol, code sent to the vendor)
+        let mut query = vec![];
+        for symbol in stocks_list {
+            let parsed = if prefix {
+                symbol.parse::<Symbol>()
+            } else {
+                Symbol::from_code(symbol)
+            };
+
+            match parsed {
+                Ok(s) => query.push((symbol, s.to_string())),
+                Err(RtError::AmbiguousSymbol { candidates, .. }) => {
+                    res.insert(symbol.to_string(), QuoteOutcome::Ambiguous { candidates });
+                }
+                Err(e) => {
+                    res.insert(
+                        symbol.to_string(),
+                        QuoteOutcome::Invalid {
+                            reason: e.to_string(),
+                        },
+                    );
+                }
+            }
+        }
+
+        if query.is_empty() {
+            return Ok(res);
+        }
+
+        let codes = query
+            .iter()
+            .map(|(_, code)| code.as_str())
+            .collect::<Vec<_>>();
+        let resp = self.get_stocks(&codes).await?;
+        let mut outcomes = self.format_response_data(resp, &codes).await?;
+
+        for (symbol, code) in query {
+            if let Some(outcome) = outcomes.remove(&code) {
+                res.insert(symbol.to_string(), outcome);
+            }
+        }
+
+        Ok(res)
+    }
+
+    fn as_fundamentals(&self) -> Option<&dyn FundamentalsSource> {
+        Some(self)
+    }
+}
+
+/// Valuation figures ride along in the quote record.
+#[async_trait]
+impl FundamentalsSource for Tencent {
+    async fn fundamentals(&self, symbol: &Symbol) -> Result<Fundamentals> {
+        let code = symbol.to_string();
+        let rt_data = match self.stocks(vec![&code], true).await?.pop() {
+            Some(rt_data) => rt_data,
+            None => return Err(RtError::UnknownSymbol(code)),
+        };
+
+        Ok(Fundamentals {
+            code:                rt_data.code,
+            pe:                  rt_data.pe,
+            pb:                  rt_data.pb,
+            market_value:        rt_data.market_value,
+            traded_market_value: rt_data.traded_market_value,
+        })
+    }
+}
 
-        self.format_response_data(resp).await.unwrap()
+impl Default for Tencent {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl Tencent {
+    pub const NAME: &'static str = "tencent";
+
     pub fn new() -> Tencent {
         // GET /?q=marketStat,sh000001,usDJI,r_hkHSI HTTP/1.1
         // Accept-Encoding: gzip, deflate, br
@@ -56,10 +161,20 @@ impl Tencent {
         Self {
             headers,
             stock_api: "https://qt.gtimg.cn/",
+            mode: ParseMode::default(),
         }
     }
 
-    pub async fn get_stocks(&self, stocks_list: Vec<&str>) -> Result<Response> {
+    /// A source that parses quotes in `mode`; strict turns any malformed
+    /// field into a `ParseError` outcome.
+    pub fn with_mode(mode: ParseMode) -> Tencent {
+        Self {
+            mode,
+            ..Self::new()
+        }
+    }
+
+    pub async fn get_stocks(&self, stocks_list: &[&str]) -> Result<Response> {
         let mut params = String::from("q=");
         for i in stocks_list {
             params = params + i + ",";
@@ -69,223 +184,573 @@ impl Tencent {
         url.pop();
 
         let client = reqwest::Client::new();
-        let resp = client.get(url).headers(self.headers.clone()).send().await?;
-
-        Ok(resp)
-    }
-
-    pub fn parse(input: &str) -> IResult<&str, ItemData> {
-        let mut rt_data = ItemData::default();
-        let (input, _) = tag("v_")(input)?;
-
-        let (input, resp) = take_while(|c| c != '=')(input)?;
-        rt_data.code = resp.to_string();
-        let (input, _) = is_not("~")(input)?;
-        let resp = input.split('~').collect::<Vec<&str>>();
-        rt_data.name = resp[1].to_string();
-        rt_data.now = resp[3].parse::<f32>().unwrap();
-        rt_data.close = resp[4].parse::<f32>().unwrap();
-        rt_data.open = resp[5].parse::<f32>().unwrap();
-        rt_data.volume = resp[6].parse::<i64>().unwrap() * 100;
-        rt_data.bid_volume = resp[7].parse::<i64>().unwrap() * 100;
-        rt_data.ask_volume = resp[8].parse::<i64>().unwrap() * 100;
-        rt_data.bid1 = resp[9].parse::<f32>().unwrap();
-        rt_data.bid1_volume = resp[10].parse::<i64>().unwrap() * 100;
-        rt_data.bid2 = resp[11].parse::<f32>().unwrap();
-        rt_data.bid2_volume = resp[12].parse::<i64>().unwrap() * 100;
-        rt_data.bid3 = resp[13].parse::<f32>().unwrap();
-        rt_data.bid3_volume = resp[14].parse::<i64>().unwrap() * 100;
-        rt_data.bid4 = resp[15].parse::<f32>().unwrap();
-        rt_data.bid4_volume = resp[16].parse::<i64>().unwrap() * 100;
-        rt_data.bid5 = resp[17].parse::<f32>().unwrap();
-        rt_data.bid5_volume = resp[18].parse::<i64>().unwrap() * 100;
-        rt_data.ask1 = resp[19].parse::<f32>().unwrap();
-        rt_data.ask1_volume = resp[20].parse::<i64>().unwrap() * 100;
-        rt_data.ask2 = resp[21].parse::<f32>().unwrap();
-        rt_data.ask2_volume = resp[22].parse::<i64>().unwrap() * 100;
-        rt_data.ask3 = resp[23].parse::<f32>().unwrap();
-        rt_data.ask3_volume = resp[24].parse::<i64>().unwrap() * 100;
-        rt_data.ask4 = resp[25].parse::<f32>().unwrap();
-        rt_data.ask4_volume = resp[26].parse::<i64>().unwrap() * 100;
-        rt_data.ask5 = resp[27].parse::<f32>().unwrap();
-        rt_data.ask5_volume = resp[28].parse::<i64>().unwrap() * 100;
-
-        match NaiveDateTime::parse_from_str(resp[30], "%Y%m%d%H%M%S") {
-            Ok(dt) => rt_data.datatime = Some(dt),
-            Err(e) => {
-                rt_data.datatime = None;
-                warn!(
-                    "parse datatime error: {}. code: {}, name: {}",
-                    e, rt_data.code, rt_data.name
-                );
-            }
+        let resp = client
+            .get(&url)
+            .headers(self.headers.clone())
+            .send()
+            .await
+            .map_err(RtError::Transport)?;
+
+        Self::check_status(resp).await
+    }
+
+    /// Fails a response with an error status as `RtError::Blocked`, with the
+    /// start of its body. Block pages and throttling notices come as 403s and
+    /// 429s as often as 200s.
+    async fn check_status(resp: Response) -> Result<Response> {
+        let status = resp.status();
+        if status.is_success() {
+            return Ok(resp);
+        }
+
+        let url = resp.url().to_string();
+        let encoding = charset(&resp);
+        let (head, complete) = response::head(resp).await;
+        let kind = match (status, response::sniff(&head, encoding, complete)) {
+            (StatusCode::TOO_MANY_REQUESTS, _) => BlockKind::RateLimited,
+            (_, Sniffed::Blocked(kind)) => kind,
+            _ => BlockKind::Unrecognized,
         };
 
-        rt_data.gain_amout = resp[31].parse::<f32>().unwrap();
-        rt_data.gain_percentage = resp[32].parse::<f32>().unwrap();
-        rt_data.high = resp[33].parse::<f32>().unwrap();
-        rt_data.low = resp[34].parse::<f32>().unwrap();
-        rt_data.total_value = resp[37].parse::<f64>().unwrap() * 10000.0;
-        rt_data.turnover = match resp[38].parse::<f32>() {
-            Ok(v) => Some(v),
-            Err(e) => {
-                warn!(
-                    "parse turnover error: {}. code: {}, name: {}",
-                    e, rt_data.code, rt_data.name
-                );
-                None
-            }
+        Err(RtError::Blocked {
+            kind,
+            status,
+            url,
+            excerpt: response::excerpt(&head, encoding),
+        })
+    }
+
+    /// Parses one `v_<code>="..."` record. Never panics, a record that doesn't
+    /// fit names the field and byte offset it failed at. Optional fields that
+    /// don't parse are left at their defaults and logged, rate limited.
+    pub fn parse(input: &str) -> std::result::Result<ItemData, RecordError> {
+        let (rt_data, diagnostics) = Self::parse_with(input, ParseMode::Lenient)?;
+        diagnostics::report(&rt_data.code, diagnostics.iter().map(|d| &d.error));
+
+        Ok(rt_data)
+    }
+
+    /// `parse` without allocating, the quote borrows from `input` and leaves
+    /// out the recent trades and vendor fields.
+    pub fn parse_ref(input: &str) -> std::result::Result<ItemDataRef<'_>, RecordError> {
+        let (rt_data, diagnostics) = Self::parse_ref_with(input, ParseMode::Lenient)?;
+        diagnostics::report(rt_data.code, diagnostics.iter().map(|d| &d.error));
+
+        Ok(rt_data)
+    }
+
+    /// `parse` in `mode`, with the fields that were left at their defaults
+    /// instead of logging them.
+    pub fn parse_with(
+        input: &str,
+        mode: ParseMode,
+    ) -> std::result::Result<(ItemData, Vec<Diagnostic>), RecordError> {
+        let record = Record::parse(input)?;
+        let mut check = Checker::new(&record, mode);
+        let rt_data = Self::read(&record, &mut check)?;
+        let recent_trades = Self::recent_trades(&record, &rt_data, &mut check)?;
+
+        let mut rt_data = rt_data.into_owned();
+        rt_data.recent_trades = recent_trades;
+        rt_data.vendor_fields = Self::vendor_fields(&record);
+        Ok((rt_data, check.diagnostics))
+    }
+
+    /// `parse_with` without allocating, but for the diagnostics.
+    pub fn parse_ref_with(
+        input: &str,
+        mode: ParseMode,
+    ) -> std::result::Result<(ItemDataRef<'_>, Vec<Diagnostic>), RecordError> {
+        let record = Record::parse(input)?;
+        let mut check = Checker::new(&record, mode);
+        let rt_data = Self::read(&record, &mut check)?;
+
+        Ok((rt_data, check.diagnostics))
+    }
+
+    /// The fields of `record` that `ItemDataRef` has a place for.
+    fn read<'a>(
+        record: &Record<'a>,
+        check: &mut Checker<'_, 'a>,
+    ) -> std::result::Result<ItemDataRef<'a>, RecordError> {
+        let layout = match Layout::detect(record) {
+            Some(layout) => layout,
+            None => return Err(record.missing(record.len())),
+        };
+        let mut rt_data = ItemDataRef {
+            name: record.field(1),
+            code: record.code,
+            source: Self::NAME,
+            ..Default::default()
         };
 
-        rt_data.pe = match resp[39].parse::<f32>() {
-            Ok(v) => {
-                if v == 0.0 {
-                    None
-                } else {
-                    Some(v)
-                }
-            }
-            Err(e) => {
-                warn!(
-                    "parse pe error: {}. code: {}, name: {}",
-                    e, rt_data.code, rt_data.name
-                );
-                None
-            }
+        // volumes are in lots, whose size depends on the instrument, and
+        // times in the exchange's local time
+        let exchange = record.code.get(..2).and_then(Exchange::from_prefix);
+        let market = exchange.map_or(Market::China, |e| e.market());
+        // HK and US records share the fields up to the market values and
+        // diverge from there
+        let china = market == Market::China;
+        if layout >= Layout::Full {
+            rt_data.vendor_type = record.field(61);
+        }
+        rt_data.instrument_type = match InstrumentType::from_vendor_type(rt_data.vendor_type) {
+            Some(kind) => kind,
+            None => exchange
+                .and_then(|e| InstrumentType::from_code(e, record.code.get(2..)?))
+                .unwrap_or_default(),
         };
+        let kind = rt_data.instrument_type;
+        let to_shares = |lots: Count| units::lots_to_shares(lots.0, market, kind);
+        let shares = |idx: usize| record.required_with(idx, to_shares);
+        rt_data.now = record.required(3)?;
+        rt_data.close = record.required(4)?;
+        rt_data.open = record.required(5)?;
+        rt_data.volume = shares(6)?;
+        rt_data.bid_volume = shares(7)?;
+        rt_data.ask_volume = shares(8)?;
+        // empty levels come as zeros
+        let levels = rt_data.bids.iter_mut().zip(&mut rt_data.asks);
+        for (i, (bid, ask)) in levels.enumerate() {
+            *bid = Level {
+                price:  record.required(9 + i * 2)?,
+                volume: shares(10 + i * 2)?,
+            };
+            *ask = Level {
+                price:  record.required(19 + i * 2)?,
+                volume: shares(20 + i * 2)?,
+            };
+        }
 
-        rt_data.pb = match resp[46].parse::<f32>() {
-            Ok(v) => {
-                if v == 0.0 {
-                    None
-                } else {
-                    Some(v)
-                }
-            }
-            Err(e) => {
-                warn!(
-                    "parse pb error: {}. code: {}, name: {}",
-                    e, rt_data.code, rt_data.name
-                );
-                None
-            }
+        match Self::stamp(record.field(30)) {
+            Some(dt) => rt_data.datatime = market.localize(&dt),
+            None => check.defaulted(record.malformed(30))?,
         };
 
-        rt_data.amplitude = resp[43].parse::<f32>().unwrap();
-        rt_data.traded_market_value = match resp[44].parse::<f32>() {
-            Ok(v) => {
-                if v == 0.0 {
-                    None
-                } else {
-                    Some(v)
-                }
-            }
-            Err(e) => {
-                warn!(
-                    "parse traded_market_value error: {}. code: {}, name: {}",
-                    e, rt_data.code, rt_data.name
-                );
-                None
-            }
+        rt_data.gain_amount = record.required(31)?;
+        rt_data.gain_percentage = record.required(32)?;
+        rt_data.high = record.required(33)?;
+        rt_data.low = record.required(34)?;
+        // on A share records field 35 is `price/volume/amount` with the exact
+        // amount and field 37 the same amount rounded to 万, HK and US records
+        // have the amount in currency units in field 37
+        let amount = match record.field(35).trim() {
+            _ if !china => None,
+            "" => None,
+            v => match v.split('/').nth(2).and_then(|v| v.parse().ok()) {
+                Some(amount) => Some(amount),
+                None => check.defaulted(record.malformed(35)).map(|_| None)?,
+            },
+        };
+        rt_data.total_value = match amount {
+            Some(v) => v,
+            None if china => record.required_with(37, units::wan_to_base)?,
+            None => record.required(37)?,
         };
+        rt_data.turnover = check.optional(38)?;
+        // zero means not available
+        rt_data.pe = check.optional::<f32>(39)?.filter(|v| *v != 0.0);
+        rt_data.amplitude = record.required(43)?;
+        // market values are in 亿
+        rt_data.traded_market_value = check
+            .optional_with(44, units::yi_to_base)?
+            .filter(|v| !v.is_zero());
+        rt_data.market_value = check
+            .optional_with(45, units::yi_to_base)?
+            .filter(|v| !v.is_zero());
 
-        rt_data.market_value = match resp[45].parse::<f32>() {
-            Ok(v) => {
-                if v == 0.0 {
-                    None
-                } else {
-                    Some(v)
-                }
-            }
-            Err(e) => {
-                warn!(
-                    "parse market_value error: {}. code: {}, name: {}",
-                    e, rt_data.code, rt_data.name
-                );
-                None
+        if china {
+            rt_data.pb = check.optional::<f32>(46)?.filter(|v| *v != 0.0);
+            rt_data.high_limit = record.required(47)?;
+            rt_data.low_limit = record.required(48)?;
+            rt_data.quantity_relative_ratio = check.optional(49)?;
+        } else {
+            // the English name and the 52 week range, there are no price
+            // limits
+            rt_data.high_52w = check.optional::<Decimal>(48)?.filter(|v| !v.is_zero());
+            rt_data.low_52w = check.optional::<Decimal>(49)?.filter(|v| !v.is_zero());
+        }
+
+        if china && layout >= Layout::Extended {
+            rt_data.entrust_different = check.optional_with(50, to_shares)?;
+            rt_data.average_price = check.optional(51)?;
+            rt_data.pe_dynamic = check.optional::<f32>(52)?.filter(|v| *v != 0.0);
+            rt_data.pe_static = check.optional::<f32>(53)?.filter(|v| *v != 0.0);
+        }
+
+        if china && layout >= Layout::Full {
+            rt_data.performance = Performance {
+                change_5d:   check.optional(62)?,
+                change_10d:  check.optional(63)?,
+                change_20d:  check.optional(64)?,
+                change_60d:  check.optional(65)?,
+                change_120d: check.optional(66)?,
+            };
+            rt_data.high_52w = check.optional::<Decimal>(67)?.filter(|v| !v.is_zero());
+            rt_data.low_52w = check.optional::<Decimal>(68)?.filter(|v| !v.is_zero());
+            rt_data.circulating_shares = check.optional::<i64>(72)?.filter(|v| *v != 0);
+            rt_data.total_shares = check.optional::<i64>(73)?.filter(|v| *v != 0);
+        }
+
+        rt_data.currency = match Self::currency_index(record, market) {
+            Some(idx) => record.field(idx),
+            None if market == Market::China => market.currency(),
+            None => {
+                check.defaulted(record.absent("currency"))?;
+                market.currency()
             }
         };
 
-        rt_data.high_limit = resp[47].parse::<f32>().unwrap();
-        rt_data.low_limit = resp[48].parse::<f32>().unwrap();
-        rt_data.quantity_relative_ratio = match resp[49].parse::<f32>() {
-            Ok(v) => Some(v),
-            Err(e) => {
-                warn!(
-                    "parse quantity_relative_ratio error: {}. code: {}, name: {}",
-                    e, rt_data.code, rt_data.name
-                );
-                None
-            }
+        Ok(rt_data)
+    }
+
+    /// Field 29, `time/price/lots/side/amount/id` entries joined by `|`.
+    fn recent_trades(
+        record: &Record,
+        rt_data: &ItemDataRef,
+        check: &mut Checker,
+    ) -> std::result::Result<Vec<Tick>, RecordError> {
+        // entries only carry the time of day
+        let date = match rt_data.datatime {
+            Some(dt) => dt.naive_local().date(),
+            None => return Ok(vec![]),
         };
+        let exchange = record.code.get(..2).and_then(Exchange::from_prefix);
+        let market = exchange.map_or(Market::China, |e| e.market());
+        // HK records have the volume there, US ones nothing
+        if market != Market::China {
+            return Ok(vec![]);
+        }
 
-        rt_data.entrust_different = match resp.get(50) {
-            Some(&v) => match v.parse::<f32>() {
-                Ok(v) => Some(v),
-                Err(e) => {
-                    warn!(
-                        "parse entrust_different error: {}. code: {}, name: {}",
-                        e, rt_data.code, rt_data.name
-                    );
-                    None
+        let mut trades = vec![];
+        for entry in record.field(29).split('|').filter(|e| !e.is_empty()) {
+            match Self::trade(entry, date, market, rt_data.instrument_type) {
+                Some(trade) => trades.push(trade),
+                None => {
+                    check.defaulted(record.malformed(29))?;
+                    return Ok(vec![]);
                 }
-            },
-            None => {
-                info!(
-                    "no entrust_different in data. code: {}, name: {}",
-                    rt_data.code, rt_data.name
-                );
-                None
             }
+        }
+
+        Ok(trades)
+    }
+
+    fn trade(entry: &str, date: NaiveDate, market: Market, kind: InstrumentType) -> Option<Tick> {
+        let mut parts = entry.split('/');
+        let time = Self::time_of_day(parts.next()?)?;
+        let price = parts.next()?.parse().ok()?;
+        let lots = parts.next()?.parse().ok()?;
+        let side = match parts.next()? {
+            "B" => TickSide::Buy,
+            "S" => TickSide::Sell,
+            "M" => TickSide::Neutral,
+            _ => return None,
+        };
+        let amount = parts.next()?.parse().ok()?;
+        let id = match parts.next() {
+            Some(id) => Some(id.parse().ok()?),
+            None => None,
         };
 
-        rt_data.average_price = match resp.get(51) {
-            Some(&v) => match v.parse::<f32>() {
-                Ok(v) => Some(v),
-                Err(e) => {
-                    warn!(
-                        "parse entrust_different error: {}. code: {}, name: {}",
-                        e, rt_data.code, rt_data.name
-                    );
-                    None
-                }
-            },
-            None => {
-                info!(
-                    "no average_price in data. code: {}, name: {}",
-                    rt_data.code, rt_data.name
-                );
-                None
+        Some(Tick {
+            time: market.localize(&date.and_time(time))?,
+            price,
+            volume: units::lots_to_shares(lots, market, kind)?,
+            amount,
+            side,
+            id,
+        })
+    }
+
+    /// The fields without a field of their own in `ItemData`, such as the
+    /// repeated volume and range and those nobody has documented.
+    fn vendor_fields(record: &Record) -> BTreeMap<usize, FieldValue> {
+        let exchange = record.code.get(..2).and_then(Exchange::from_prefix);
+        let market = exchange.map_or(Market::China, |e| e.market());
+        let layout = Layout::detect(record);
+        let currency = Self::currency_index(record, market);
+
+        record
+            .fields()
+            .enumerate()
+            .filter(|(i, _)| !layout.is_some_and(|l| l.reads(*i, market)) && Some(*i) != currency)
+            .map(|(i, v)| (i, v.trim()))
+            .filter(|(_, v)| !v.is_empty())
+            .map(|(i, v)| (i, FieldValue::infer(v)))
+            .collect()
+    }
+
+    /// Where the currency is on the records that carry one, field 35 on US
+    /// records and 75 on the longest HK ones. A share quotes are always in
+    /// yuan.
+    fn currency_index(record: &Record, market: Market) -> Option<usize> {
+        let index = match market {
+            Market::China => return None,
+            Market::HongKong => 75,
+            Market::UnitedStates => 35,
+        };
+        Some(index).filter(|i| CURRENCIES.contains(&record.field(*i)))
+    }
+
+    /// Field 30, `20220419161403` on A share records, `2022/04/19 16:08:10` on
+    /// HK and `2022-04-19 16:00:02` on US ones.
+    fn stamp(v: &str) -> Option<NaiveDateTime> {
+        let b = v.as_bytes();
+        let [year, month, day, hour, min, sec] = match b.len() {
+            14 => [0..4, 4..6, 6..8, 8..10, 10..12, 12..14],
+            19 if matches!(b[4], b'/' | b'-')
+                && b[7] == b[4]
+                && b[10] == b' '
+                && b[13] == b':'
+                && b[16] == b':' =>
+            {
+                [0..4, 5..7, 8..10, 11..13, 14..16, 17..19]
             }
+            _ => return None,
         };
+        let num = |range: std::ops::Range<usize>| digits(&b[range]);
 
-        Ok(("", rt_data))
+        let date = NaiveDate::from_ymd_opt(num(year)? as i32, num(month)?, num(day)?)?;
+        date.and_hms_opt(num(hour)?, num(min)?, num(sec)?)
     }
 
-    pub async fn format_response_data(&self, response: reqwest::Response) -> Result<Vec<ItemData>> {
-        let data = response.text().await?;
-        let data_details = data.split(";");
+    /// `14:57:00`, the time of a recent trade.
+    fn time_of_day(v: &str) -> Option<NaiveTime> {
+        match v.as_bytes() {
+            [h @ .., b':', m1, m2, b':', s1, s2] if (1..=2).contains(&h.len()) => {
+                NaiveTime::from_hms_opt(digits(h)?, digits(&[*m1, *m2])?, digits(&[*s1, *s2])?)
+            }
+            _ => None,
+        }
+    }
+
+    pub async fn format_response_data(
+        &self,
+        response: reqwest::Response,
+        stocks_list: &[&str],
+    ) -> Result<HashMap<String, QuoteOutcome>> {
+        self.stream_response_data(response, stocks_list)
+            .try_collect()
+            .await
+    }
 
-        let mut res = vec![];
+    /// Like `format_response_data`, but yields each outcome as soon as its
+    /// record is complete instead of waiting for the whole body. Symbols the
+    /// body never answered come last, as `NotFound`.
+    ///
+    /// A body that isn't quotes, such as a block page, is a single
+    /// `RtError::Blocked`.
+    pub fn stream_response_data(
+        &self,
+        response: reqwest::Response,
+        stocks_list: &[&str],
+    ) -> impl Stream<Item = Result<(String, QuoteOutcome)>> {
+        let encoding = charset(&response);
+        let body = Body {
+            parser: ResponseParser::new(stocks_list, encoding).with_mode(self.mode),
+            encoding,
+            head: Some(vec![]),
+            status: response.status(),
+            url: response.url().to_string(),
+            response: Some(response),
+            ready: VecDeque::new(),
+        };
 
-        for i in data_details {
-            match Self::parse(i.trim()) {
-                Ok((_, rt_data)) => {
-                    res.push(rt_data);
+        stream::unfold(body, |mut body| async move {
+            loop {
+                if let Some(outcome) = body.ready.pop_front() {
+                    return Some((Ok(outcome), body));
                 }
-                Err(e) => {
-                    warn!("parse error: {}", e);
+
+                let chunk = match body.response.as_mut()?.chunk().await {
+                    Ok(chunk) => chunk,
+                    Err(e) => {
+                        body.response = None;
+                        return Some((Err(RtError::Decode(e)), body));
+                    }
+                };
+                let received_at = Utc::now();
+                let complete = chunk.is_none();
+                let chunk = chunk.unwrap_or_default();
+
+                // hold the start of the body back until it's clear it is quotes
+                let mut outcomes = match body.head.take() {
+                    Some(mut head) => {
+                        head.extend_from_slice(&chunk);
+                        match response::sniff(&head, body.encoding, complete) {
+                            Sniffed::Quotes => body.parser.push(&head, received_at),
+                            Sniffed::Undecided => {
+                                body.head = Some(head);
+                                continue;
+                            }
+                            Sniffed::Blocked(kind) => {
+                                body.response = None;
+                                let e = RtError::Blocked {
+                                    kind,
+                                    status: body.status,
+                                    url: body.url.clone(),
+                                    excerpt: response::excerpt(&head, body.encoding),
+                                };
+                                return Some((Err(e), body));
+                            }
+                        }
+                    }
+                    None => body.parser.push(&chunk, received_at),
+                };
+                if complete {
+                    body.response = None;
+                    outcomes.extend(body.parser.finish(received_at));
                 }
-            };
+
+                body.ready.extend(outcomes);
+            }
+        })
+    }
+
+    /// The `v_...` records of a response body. With `parse_ref` a whole body
+    /// parses without allocating.
+    pub fn records(data: &str) -> impl Iterator<Item = &str> {
+        data.split(';').map(str::trim).filter(|i| !i.is_empty())
+    }
+
+    /// Maps every record back to the symbol it was requested as; see
+    /// `ResponseParser`. `received_at` is when `data` was read.
+    pub fn parse_response(
+        data: &str,
+        stocks_list: &[&str],
+        received_at: DateTime<Utc>,
+    ) -> HashMap<String, QuoteOutcome> {
+        let mut parser = ResponseParser::utf8(stocks_list);
+        let mut res = parser.push(data.as_bytes(), received_at);
+        res.extend(parser.finish(received_at));
+
+        res.into_iter().collect()
+    }
+
+    /// A suspended code still gets a record, but nothing has traded, both
+    /// sides of the book are empty and the stamp is from an earlier day at
+    /// the exchange than `received_at`. Before the opening auction every code
+    /// looks like that but for the same day's stamp.
+    fn is_suspended(rt_data: &ItemData, received_at: DateTime<Utc>) -> bool {
+        rt_data.volume == 0
+            && rt_data.open.is_zero()
+            && rt_data.book.bids.is_empty()
+            && rt_data.book.asks.is_empty()
+            && rt_data.datatime.is_some_and(|dt| {
+                let received = received_at.with_timezone(dt.offset());
+                dt.naive_local().date() < received.naive_local().date()
+            })
+    }
+}
+
+/// A response body being read by `stream_response_data`.
+struct Body {
+    parser:   ResponseParser,
+    encoding: &'static Encoding,
+    /// The start of the body while it's unclear whether it is quotes.
+    head:     Option<Vec<u8>>,
+    status:   StatusCode,
+    url:      String,
+    /// `None` once the body is read.
+    response: Option<Response>,
+    ready:    VecDeque<(String, QuoteOutcome)>,
+}
+
+/// The encoding named by the `Content-Type` charset, UTF-8 without one.
+/// Tencent sends GBK.
+fn charset(response: &Response) -> &'static Encoding {
+    response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| {
+            v.split(';')
+                .filter_map(|param| param.split_once('='))
+                .find(|(key, _)| key.trim().eq_ignore_ascii_case("charset"))
+                .and_then(|(_, label)| {
+                    Encoding::for_label(label.trim().trim_matches('"').as_bytes())
+                })
+        })
+        .unwrap_or(UTF_8)
+}
+
+/// The number `bytes` spell in ASCII digits.
+fn digits(bytes: &[u8]) -> Option<u32> {
+    bytes.iter().try_fold(0, |n: u32, c| {
+        c.is_ascii_digit().then(|| n * 10 + u32::from(c - b'0'))
+    })
+}
+
+/// A share count, which HK records send with a zero fraction, e.g.
+/// `14753389.0`.
+struct Count(i64);
+
+impl FromField for Count {
+    fn from_field(field: &str) -> Option<Count> {
+        let whole = match field.split_once('.') {
+            Some((whole, fraction)) if fraction.bytes().all(|b| b == b'0') => whole,
+            Some(_) => return None,
+            None => field,
+        };
+        i64::from_field(whole).map(Count)
+    }
+}
+
+/// Generations of the quote record, told apart by field count and markers.
+/// Each one extends the previous, later fields are left at their defaults on
+/// older records.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Layout {
+    /// Up to the price limits and volume ratio, e.g. 2015 captures.
+    Basic,
+    /// Adds 委差, the average price and dynamic and static PE.
+    Extended,
+    /// Adds the type marker, performance, the 52 week range and share counts.
+    Full,
+}
+
+impl Layout {
+    /// `None` when the record is too short for any known layout. Counts
+    /// include the market id before the first `~` and the empty field after
+    /// the last one.
+    pub fn detect(record: &Record) -> Option<Layout> {
+        let marked = record
+            .field(61)
+            .starts_with(|c: char| c.is_ascii_uppercase());
+        match record.len() {
+            n if n < 50 => None,
+            n if n >= 75 && marked => Some(Layout::Full),
+            n if n >= 55 => Some(Layout::Extended),
+            _ => Some(Layout::Basic),
         }
+    }
 
-        Ok(res)
+    /// Whether field `index` of a record from `market` goes into a field of
+    /// its own, the market id counting as read since the code implies it.
+    fn reads(self, index: usize, market: Market) -> bool {
+        let china = market == Market::China;
+        match index {
+            0..=34 | 37..=39 | 43..=45 | 48 | 49 => true,
+            35 | 46 | 47 => china,
+            50..=53 => china && self >= Layout::Extended,
+            61..=68 | 72 | 73 => china && self >= Layout::Full,
+            _ => false,
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use chrono::TimeZone;
+    use rust_decimal_macros::dec;
+
     use super::*;
+    use crate::{order_book::OrderBook, status::TradingStatus};
+
+    fn level(price: Decimal, volume: i64) -> Level {
+        Level { price, volume }
+    }
 
     // #[actix_web::test]
     // async fn test_tencent() -> Result<()> {
@@ -316,111 +781,209 @@ mod tests {
                       19~9717553125~11625383750~-42.55~-5.40~9717553125~\"";
 
         match Tencent::parse(parse1) {
-            Ok((_, rt_data)) => {
+            Ok(rt_data) => {
                 assert_eq!(rt_data.code, "sz000001");
                 assert_eq!(rt_data.name, "平安银行");
-                assert_eq!(rt_data.now, 15.81);
-                assert_eq!(rt_data.close, 15.90);
-                assert_eq!(rt_data.open, 15.90);
+                assert_eq!(rt_data.display_name, "平安银行");
+                assert_eq!(rt_data.status, TradingStatus::Trading);
+                assert_eq!(rt_data.now, dec!(15.81));
+                assert_eq!(rt_data.close, dec!(15.90));
+                assert_eq!(rt_data.open, dec!(15.90));
                 assert_eq!(rt_data.volume, 82177200);
                 assert_eq!(rt_data.bid_volume, 38188100);
                 assert_eq!(rt_data.ask_volume, 43989200);
-                assert_eq!(rt_data.bid1, 15.81);
-                assert_eq!(rt_data.bid1_volume, 50600);
-                assert_eq!(rt_data.bid2, 15.80);
-                assert_eq!(rt_data.bid2_volume, 143900);
-                assert_eq!(rt_data.bid3, 15.79);
-                assert_eq!(rt_data.bid3_volume, 214500);
-                assert_eq!(rt_data.bid4, 15.78);
-                assert_eq!(rt_data.bid4_volume, 393200);
-                assert_eq!(rt_data.bid5, 15.77);
-                assert_eq!(rt_data.bid5_volume, 68700);
-                assert_eq!(rt_data.ask1, 15.82);
-                assert_eq!(rt_data.ask1_volume, 34300);
-                assert_eq!(rt_data.ask2, 15.83);
-                assert_eq!(rt_data.ask2_volume, 266500);
-                assert_eq!(rt_data.ask3, 15.84);
-                assert_eq!(rt_data.ask3_volume, 144900);
-                assert_eq!(rt_data.ask4, 15.85);
-                assert_eq!(rt_data.ask4_volume, 268100);
-                assert_eq!(rt_data.ask5, 15.86);
-                assert_eq!(rt_data.ask5_volume, 115700);
+                assert_eq!(
+                    rt_data.book,
+                    OrderBook {
+                        bids: vec![
+                            level(dec!(15.81), 50600),
+                            level(dec!(15.80), 143900),
+                            level(dec!(15.79), 214500),
+                            level(dec!(15.78), 393200),
+                            level(dec!(15.77), 68700),
+                        ],
+                        asks: vec![
+                            level(dec!(15.82), 34300),
+                            level(dec!(15.83), 266500),
+                            level(dec!(15.84), 144900),
+                            level(dec!(15.85), 268100),
+                            level(dec!(15.86), 115700),
+                        ],
+                    }
+                );
                 assert_eq!(
                     rt_data.datatime,
-                    Some(NaiveDateTime::parse_from_str("20220419161403", "%Y%m%d%H%M%S").unwrap())
+                    Market::China.localize(
+                        &NaiveDateTime::parse_from_str("20220419161403", "%Y%m%d%H%M%S").unwrap()
+                    )
                 );
-                assert_eq!(rt_data.gain_amout, -0.09);
+                assert_eq!(rt_data.gain_amount, dec!(-0.09));
                 assert_eq!(rt_data.gain_percentage, -0.57);
-                assert_eq!(rt_data.high, 15.97);
-                assert_eq!(rt_data.low, 15.62);
-                assert_eq!(rt_data.total_value, 1294230000.0);
+                assert_eq!(rt_data.high, dec!(15.97));
+                assert_eq!(rt_data.low, dec!(15.62));
+                assert_eq!(rt_data.total_value, dec!(1294226951));
                 assert_eq!(rt_data.turnover, Some(0.42));
                 assert_eq!(rt_data.pe, Some(8.44));
                 assert_eq!(rt_data.pb, Some(0.94));
                 assert_eq!(rt_data.amplitude, 2.2);
-                assert_eq!(rt_data.traded_market_value, Some(3068.01));
-                assert_eq!(rt_data.market_value, Some(3068.08));
-                assert_eq!(rt_data.high_limit, 17.49);
-                assert_eq!(rt_data.low_limit, 14.31);
+                assert_eq!(rt_data.traded_market_value, Some(dec!(306801000000)));
+                assert_eq!(rt_data.market_value, Some(dec!(306808000000)));
+                assert_eq!(rt_data.high_limit, dec!(17.49));
+                assert_eq!(rt_data.low_limit, dec!(14.31));
                 assert_eq!(rt_data.quantity_relative_ratio, Some(0.79));
-                assert_eq!(rt_data.entrust_different, Some(414.0));
-                assert_eq!(rt_data.average_price, Some(15.75));
+                assert_eq!(rt_data.entrust_different, Some(41400));
+                assert_eq!(rt_data.average_price, Some(dec!(15.75)));
+                assert_eq!(rt_data.pe_dynamic, Some(8.44));
+                assert_eq!(rt_data.pe_static, Some(8.44));
+                assert_eq!(rt_data.vendor_type, "GP-A");
+                assert_eq!(rt_data.instrument_type, InstrumentType::Stock);
+                assert_eq!(
+                    rt_data.performance,
+                    Performance {
+                        change_5d:   Some(-4.07),
+                        change_10d:  Some(-0.69),
+                        change_20d:  Some(1.14),
+                        change_60d:  Some(9.19),
+                        change_120d: Some(0.74),
+                    }
+                );
+                assert_eq!(rt_data.high_52w, Some(dec!(25.16)));
+                assert_eq!(rt_data.low_52w, Some(dec!(13.22)));
+                assert_eq!(rt_data.circulating_shares, Some(19405522500));
+                assert_eq!(rt_data.total_shares, Some(19405918750));
+                assert_eq!(rt_data.currency, "CNY");
+                assert!(rt_data.recent_trades.is_empty());
+                assert_eq!(
+                    rt_data.vendor_fields,
+                    BTreeMap::from([
+                        (36, FieldValue::Integer(821772)),
+                        (41, FieldValue::Decimal(dec!(15.97))),
+                        (42, FieldValue::Decimal(dec!(15.62))),
+                        (56, FieldValue::Decimal(dec!(1.33))),
+                        (57, FieldValue::Decimal(dec!(129422.6951))),
+                        (58, FieldValue::Decimal(dec!(0.0000))),
+                        (59, FieldValue::Integer(0)),
+                        (69, FieldValue::Decimal(dec!(0.38))),
+                        (70, FieldValue::Decimal(dec!(7.55))),
+                        (71, FieldValue::Decimal(dec!(-3.18))),
+                        (74, FieldValue::Decimal(dec!(2.43))),
+                        (75, FieldValue::Decimal(dec!(-23.25))),
+                        (76, FieldValue::Integer(19405522500)),
+                    ])
+                );
             }
             Err(e) => {
                 panic!("parse error: {}", e);
             }
         }
 
+        let trades = parse1.replace(
+            "~1157~~",
+            "~1157~15:00:03/15.81/1929/S/3049749/29540|14:56:57/15.82/20/B/31640/29446~",
+        );
+        let rt_data = Tencent::parse(&trades).unwrap();
+        let at =
+            |s| Market::China.localize(&NaiveDateTime::parse_from_str(s, "%Y%m%d%H%M%S").unwrap());
+        assert_eq!(
+            rt_data.recent_trades,
+            [
+                Tick {
+                    time:   at("20220419150003").unwrap(),
+                    price:  dec!(15.81),
+                    volume: 192900,
+                    amount: dec!(3049749),
+                    side:   TickSide::Sell,
+                    id:     Some(29540),
+                },
+                Tick {
+                    time:   at("20220419145657").unwrap(),
+                    price:  dec!(15.82),
+                    volume: 2000,
+                    amount: dec!(31640),
+                    side:   TickSide::Buy,
+                    id:     Some(29446),
+                },
+            ]
+        );
+        let garbled = Tencent::parse_with(&trades.replace("/B/", "/?/"), ParseMode::Strict);
+        assert_eq!(garbled.unwrap_err().index, Some(29));
+
+        // A share quotes are in yuan, a type field that looks like a code
+        // doesn't count
+        let etf = parse1.replace("~GP-A~", "~ETF~");
+        assert_eq!(Tencent::parse(&etf).unwrap().currency, "CNY");
+
+        let quote = Tencent::parse_ref(parse1).unwrap();
+        assert_eq!((quote.code, quote.name), ("sz000001", "平安银行"));
+        assert_eq!(quote.bids[0], level(dec!(15.81), 50600));
+        assert_eq!(quote.vendor_type, "GP-A");
+        let owned = ItemData::from(quote);
+        assert_eq!(owned.book, Tencent::parse(parse1).unwrap().book);
+        assert_eq!(owned.source, "tencent");
+
         match Tencent::parse(parse2) {
-            Ok((_, rt_data)) => {
+            Ok(rt_data) => {
                 assert_eq!(rt_data.code, "sz000002");
                 assert_eq!(rt_data.name, "万  科Ａ");
-                assert_eq!(rt_data.now, 20.66);
-                assert_eq!(rt_data.close, 20.53);
-                assert_eq!(rt_data.open, 20.54);
+                assert_eq!(rt_data.display_name, "万科A");
+                assert!(rt_data.risk_flags.is_empty());
+                assert_eq!(rt_data.now, dec!(20.66));
+                assert_eq!(rt_data.close, dec!(20.53));
+                assert_eq!(rt_data.open, dec!(20.54));
                 assert_eq!(rt_data.volume, 119979800);
                 assert_eq!(rt_data.bid_volume, 62036800);
                 assert_eq!(rt_data.ask_volume, 57943000);
-                assert_eq!(rt_data.bid1, 20.66);
-                assert_eq!(rt_data.bid1_volume, 255400);
-                assert_eq!(rt_data.bid2, 20.65);
-                assert_eq!(rt_data.bid2_volume, 47400);
-                assert_eq!(rt_data.bid3, 20.64);
-                assert_eq!(rt_data.bid3_volume, 19400);
-                assert_eq!(rt_data.bid4, 20.63);
-                assert_eq!(rt_data.bid4_volume, 47600);
-                assert_eq!(rt_data.bid5, 20.62);
-                assert_eq!(rt_data.bid5_volume, 7500);
-                assert_eq!(rt_data.ask1, 20.67);
-                assert_eq!(rt_data.ask1_volume, 33300);
-                assert_eq!(rt_data.ask2, 20.68);
-                assert_eq!(rt_data.ask2_volume, 113600);
-                assert_eq!(rt_data.ask3, 20.69);
-                assert_eq!(rt_data.ask3_volume, 66800);
-                assert_eq!(rt_data.ask4, 20.7);
-                assert_eq!(rt_data.ask4_volume, 672000);
-                assert_eq!(rt_data.ask5, 20.71);
-                assert_eq!(rt_data.ask5_volume, 50400);
+                assert_eq!(
+                    rt_data.book,
+                    OrderBook {
+                        bids: vec![
+                            level(dec!(20.66), 255400),
+                            level(dec!(20.65), 47400),
+                            level(dec!(20.64), 19400),
+                            level(dec!(20.63), 47600),
+                            level(dec!(20.62), 7500),
+                        ],
+                        asks: vec![
+                            level(dec!(20.67), 33300),
+                            level(dec!(20.68), 113600),
+                            level(dec!(20.69), 66800),
+                            level(dec!(20.7), 672000),
+                            level(dec!(20.71), 50400),
+                        ],
+                    }
+                );
                 assert_eq!(
                     rt_data.datatime,
-                    Some(NaiveDateTime::parse_from_str("20220419161403", "%Y%m%d%H%M%S").unwrap())
+                    Market::China.localize(
+                        &NaiveDateTime::parse_from_str("20220419161403", "%Y%m%d%H%M%S").unwrap()
+                    )
                 );
-                assert_eq!(rt_data.gain_amout, 0.13);
+                assert_eq!(rt_data.gain_amount, dec!(0.13));
                 assert_eq!(rt_data.gain_percentage, 0.63);
-                assert_eq!(rt_data.high, 20.7);
-                assert_eq!(rt_data.low, 19.67);
-                assert_eq!(rt_data.total_value, 2423550000.0);
+                assert_eq!(rt_data.high, dec!(20.7));
+                assert_eq!(rt_data.low, dec!(19.67));
+                assert_eq!(rt_data.total_value, dec!(2423548170));
                 assert_eq!(rt_data.turnover, Some(1.23));
                 assert_eq!(rt_data.pe, Some(10.66));
                 assert_eq!(rt_data.pb, Some(1.02));
                 assert_eq!(rt_data.amplitude, 5.02);
-                assert_eq!(rt_data.traded_market_value, Some(2007.65));
-                assert_eq!(rt_data.market_value, Some(2401.8));
-                assert_eq!(rt_data.high_limit, 22.58);
-                assert_eq!(rt_data.low_limit, 18.48);
+                assert_eq!(rt_data.traded_market_value, Some(dec!(200765000000)));
+                assert_eq!(rt_data.market_value, Some(dec!(240180000000)));
+                assert_eq!(rt_data.high_limit, dec!(22.58));
+                assert_eq!(rt_data.low_limit, dec!(18.48));
                 assert_eq!(rt_data.quantity_relative_ratio, Some(0.78));
-                assert_eq!(rt_data.entrust_different, Some(-5588.0));
-                assert_eq!(rt_data.average_price, Some(20.2));
+                assert_eq!(rt_data.entrust_different, Some(-558800));
+                assert_eq!(rt_data.average_price, Some(dec!(20.2)));
+                assert_eq!(rt_data.pe_dynamic, Some(10.66));
+                assert_eq!(rt_data.pe_static, Some(10.66));
+                assert_eq!(rt_data.vendor_type, "GP-A");
+                assert_eq!(rt_data.performance.change_5d, Some(4.55));
+                assert_eq!(rt_data.performance.change_120d, Some(1.96));
+                assert_eq!(rt_data.high_52w, Some(dec!(27.83)));
+                assert_eq!(rt_data.low_52w, Some(dec!(14.43)));
+                // A shares only, the H shares are in the total
+                assert_eq!(rt_data.circulating_shares, Some(9717553125));
+                assert_eq!(rt_data.total_shares, Some(11625383750));
             }
             Err(e) => {
                 panic!("parse error: {}", e);
@@ -428,6 +991,105 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_response_outcomes() {
+        let data = "v_sh518801=\"1~国泰申赎~518801~2.229~2.229~0.000~0~0~0~0.000~0~0.000~0~0.\
+                    000~0~0.000~0~0.000~0~0.000~0~0.000~0~0.000~0~0.000~0~0.\
+                    000~0~~20151224150221~0.000~0.00~0.000~0.000~2.230/0/0~0~0~~~~0.000~0.000~0.\
+                    00~~~0.000~2.452~2.006~\";\nv_pv_none_match=\"1\";\n";
+
+        let received_at = Utc.ymd(2015, 12, 25).and_hms(1, 30, 0);
+        let res = Tencent::parse_response(data, &["SH518801", "sh999999", "sz000001"], received_at);
+        assert_eq!(res.len(), 3);
+        match res.get("SH518801") {
+            Some(QuoteOutcome::Suspended(rt_data)) => assert_eq!(rt_data.code, "sh518801"),
+            other => panic!("expected suspended quote, got {:?}", other),
+        }
+        assert!(matches!(res.get("sh999999"), Some(QuoteOutcome::NotFound)));
+        assert!(matches!(res.get("sz000001"), Some(QuoteOutcome::NotFound)));
+    }
+
+    #[actix_web::test]
+    async fn test_stream_response_data() {
+        let data = "v_sh518801=\"1~国泰申赎~518801~2.229~2.229~0.000~0~0~0~0.000~0~0.000~0~0.\
+                    000~0~0.000~0~0.000~0~0.000~0~0.000~0~0.000~0~0.000~0~0.\
+                    000~0~~20151224150221~0.000~0.00~0.000~0.000~2.230/0/0~0~0~~~~0.000~0.000~0.\
+                    00~~~0.000~2.452~2.006~\";\n";
+        let (gbk, ..) = encoding_rs::GBK.encode(data);
+        let response = http::Response::builder()
+            .header("Content-Type", "text/html; charset=GBK")
+            .body(gbk.into_owned())
+            .unwrap();
+
+        let outcomes = Tencent::new()
+            .stream_response_data(response.into(), &["sh518801", "sz000001"])
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(outcomes.len(), 2);
+        match &outcomes[0] {
+            (symbol, QuoteOutcome::Suspended(rt_data)) => {
+                assert_eq!(symbol, "sh518801");
+                assert_eq!(rt_data.name, "国泰申赎");
+                assert!(rt_data.received_at.is_some());
+            }
+            other => panic!("expected suspended quote, got {:?}", other),
+        }
+        assert!(matches!(&outcomes[1], (s, QuoteOutcome::NotFound) if s == "sz000001"));
+    }
+
+    #[actix_web::test]
+    async fn test_blocked_responses() {
+        for (body, kind) in [
+            ("<html><body>403 Forbidden</body></html>", BlockKind::Html),
+            ("", BlockKind::Empty),
+            ("访问过于频繁", BlockKind::RateLimited),
+        ] {
+            let response = http::Response::builder().body(body).unwrap();
+            let res = Tencent::new()
+                .format_response_data(response.into(), &["sz000001"])
+                .await;
+            match res {
+                Err(RtError::Blocked {
+                    kind: blocked,
+                    status,
+                    excerpt,
+                    ..
+                }) => {
+                    assert_eq!(blocked, kind);
+                    assert_eq!(status, StatusCode::OK);
+                    assert_eq!(excerpt, body);
+                }
+                other => panic!("expected {:?}, got {:?}", kind, other),
+            }
+        }
+
+        // error statuses keep their body too
+        for (status, body, kind) in [
+            (403, "<html><body>Forbidden</body></html>", BlockKind::Html),
+            (429, "slow down", BlockKind::RateLimited),
+            (503, "", BlockKind::Empty),
+            (500, "v_sz000001=\"1\";", BlockKind::Unrecognized),
+        ] {
+            let response = http::Response::builder().status(status).body(body).unwrap();
+            match Tencent::check_status(response.into()).await {
+                Err(RtError::Blocked {
+                    kind: blocked,
+                    status: got,
+                    excerpt,
+                    ..
+                }) => {
+                    assert_eq!(blocked, kind);
+                    assert_eq!(got.as_u16(), status);
+                    assert_eq!(excerpt, body);
+                }
+                other => panic!("expected {:?}, got {:?}", kind, other),
+            }
+        }
+        let ok = http::Response::builder().body("v_sz000001=\"1\";").unwrap();
+        assert!(Tencent::check_status(ok.into()).await.is_ok());
+    }
+
     #[test]
     fn test_parse_data2() {
         let data = "v_sh518801=\"1~国泰申赎~518801~2.229~2.229~0.000~0~0~0~0.000~0~0.000~0~0.\
@@ -435,59 +1097,214 @@ mod tests {
                     000~0~~20151224150221~0.000~0.00~0.000~0.000~2.230/0/0~0~0~~~~0.000~0.000~0.\
                     00~~~0.000~2.452~2.006~\"";
         match Tencent::parse(data) {
-            Ok((_, rt_data)) => {
+            Ok(rt_data) => {
                 assert_eq!(rt_data.code, "sh518801");
                 assert_eq!(rt_data.name, "国泰申赎");
-                assert_eq!(rt_data.now, 2.229);
-                assert_eq!(rt_data.close, 2.229);
-                assert_eq!(rt_data.open, 0.0);
+                assert_eq!(rt_data.now, dec!(2.229));
+                assert_eq!(rt_data.close, dec!(2.229));
+                assert_eq!(rt_data.open, dec!(0.0));
                 assert_eq!(rt_data.volume, 0);
                 assert_eq!(rt_data.bid_volume, 0);
                 assert_eq!(rt_data.ask_volume, 0);
-                assert_eq!(rt_data.bid1, 0.0);
-                assert_eq!(rt_data.bid1_volume, 0);
-                assert_eq!(rt_data.bid2, 0.0);
-                assert_eq!(rt_data.bid2_volume, 0);
-                assert_eq!(rt_data.bid3, 0.0);
-                assert_eq!(rt_data.bid3_volume, 0);
-                assert_eq!(rt_data.bid4, 0.0);
-                assert_eq!(rt_data.bid4_volume, 0);
-                assert_eq!(rt_data.bid5, 0.0);
-                assert_eq!(rt_data.bid5_volume, 0);
-                assert_eq!(rt_data.ask1, 0.0);
-                assert_eq!(rt_data.ask1_volume, 0);
-                assert_eq!(rt_data.ask2, 0.0);
-                assert_eq!(rt_data.ask2_volume, 0);
-                assert_eq!(rt_data.ask3, 0.0);
-                assert_eq!(rt_data.ask3_volume, 0);
-                assert_eq!(rt_data.ask4, 0.0);
-                assert_eq!(rt_data.ask4_volume, 0);
-                assert_eq!(rt_data.ask5, 0.0);
-                assert_eq!(rt_data.ask5_volume, 0);
+                assert_eq!(rt_data.book, OrderBook::default());
+                assert_eq!(Tencent::parse_ref(data).unwrap().bids().count(), 0);
                 assert_eq!(
                     rt_data.datatime,
-                    Some(NaiveDateTime::parse_from_str("20151224150221", "%Y%m%d%H%M%S").unwrap())
+                    Market::China.localize(
+                        &NaiveDateTime::parse_from_str("20151224150221", "%Y%m%d%H%M%S").unwrap()
+                    )
                 );
-                assert_eq!(rt_data.gain_amout, 0.000);
+                assert_eq!(rt_data.gain_amount, dec!(0.000));
                 assert_eq!(rt_data.gain_percentage, 0.000);
-                assert_eq!(rt_data.high, 0.000);
-                assert_eq!(rt_data.low, 0.000);
-                assert_eq!(rt_data.total_value, 0.000);
+                assert_eq!(rt_data.high, dec!(0.000));
+                assert_eq!(rt_data.low, dec!(0.000));
+                assert_eq!(rt_data.total_value, dec!(0.000));
                 assert_eq!(rt_data.turnover, None);
                 assert_eq!(rt_data.pe, None);
                 assert_eq!(rt_data.pb, None);
                 assert_eq!(rt_data.amplitude, 0.0);
                 assert_eq!(rt_data.traded_market_value, None);
                 assert_eq!(rt_data.market_value, None);
-                assert_eq!(rt_data.high_limit, 2.452);
-                assert_eq!(rt_data.low_limit, 2.006);
+                assert_eq!(rt_data.high_limit, dec!(2.452));
+                assert_eq!(rt_data.low_limit, dec!(2.006));
                 assert_eq!(rt_data.quantity_relative_ratio, None);
                 assert_eq!(rt_data.entrust_different, None);
                 assert_eq!(rt_data.average_price, None);
+                assert_eq!(rt_data.pe_dynamic, None);
+                assert_eq!(rt_data.vendor_type, "");
+                // no type field on the old record, 518xxx is an ETF range
+                assert_eq!(rt_data.instrument_type, InstrumentType::Etf);
+                assert_eq!(rt_data.performance, Performance::default());
+                assert_eq!(rt_data.high_52w, None);
+                assert_eq!(rt_data.total_shares, None);
+                assert_eq!(rt_data.currency, "CNY");
             }
             Err(e) => {
                 panic!("parse error: {}", e);
             }
         }
+
+        // judged by the exchange's date when received, not the clock, so a
+        // stored body reads the same later; before the opening auction a
+        // trading code looks the same, but is stamped the same day
+        let rt_data = Tencent::parse(data).unwrap();
+        assert_eq!(rt_data.status, TradingStatus::Trading);
+        let next_day = Utc.ymd(2015, 12, 24).and_hms(16, 0, 0);
+        assert!(Tencent::is_suspended(&rt_data, next_day));
+        let same_day = next_day - chrono::Duration::seconds(1);
+        assert!(!Tencent::is_suspended(&rt_data, same_day));
+    }
+
+    #[test]
+    fn test_layout() {
+        let detect = |fields: &[&str]| {
+            let input = format!("v_sz000001=\"{}\"", fields.join("~"));
+            Layout::detect(&Record::parse(&input).unwrap())
+        };
+        let mut fields = vec!["0"; 78];
+        assert_eq!(detect(&fields), Some(Layout::Extended));
+        fields[61] = "GP-A";
+        assert_eq!(detect(&fields), Some(Layout::Full));
+        assert_eq!(detect(&fields[..60]), Some(Layout::Extended));
+        assert_eq!(detect(&fields[..50]), Some(Layout::Basic));
+        assert_eq!(detect(&fields[..49]), None);
+
+        // a current record cut after the static PE
+        let extended = "v_sz000001=\"51~平安银行~000001~15.81~15.90~15.90~821772~381881~439892~15.\
+                        81~506~15.80~1439~15.79~2145~15.78~3932~15.77~687~15.82~343~15.83~2665~15.\
+                        84~1449~15.85~2681~15.86~1157~~20220419161403~-0.09~-0.57~15.97~15.62~15.\
+                        81/821772/1294226951~821772~129423~0.42~8.44~~15.97~15.62~2.20~3068.01~3068.\
+                        08~0.94~17.49~14.31~0.79~414~15.75~8.44~8.44~\"";
+        let rt_data = Tencent::parse(extended).unwrap();
+        assert_eq!(rt_data.pe_static, Some(8.44));
+        assert_eq!(rt_data.vendor_type, "");
+        assert_eq!(rt_data.total_shares, None);
+        assert_eq!(rt_data.instrument_type, InstrumentType::Stock);
+
+        // bond lots are 10 units, not 100 shares
+        let bond = extended.replace("sz000001", "sz128001");
+        let rt_data = Tencent::parse(&bond).unwrap();
+        assert_eq!(rt_data.instrument_type, InstrumentType::ConvertibleBond);
+        assert_eq!(rt_data.volume, 8217720);
+        assert_eq!(rt_data.book.bids[0].volume, 5060);
+        assert_eq!(rt_data.entrust_different, Some(4140));
+
+        for broken in [
+            "v_sz000001=\"51~平安银行~000001~15.81\"",
+            "v_sz000001=\"\"",
+            "v_sz000001",
+            "",
+        ] {
+            assert!(Tencent::parse(broken).is_err(), "{}", broken);
+        }
+
+        let garbled = extended.replace("15.90~15.90", "15.90~n/a");
+        let e = Tencent::parse(&garbled).unwrap_err();
+        assert_eq!(e.kind, RecordErrorKind::Malformed);
+        assert_eq!((e.index, e.name, e.offset), (Some(5), "open", 47));
+
+        // a malformed optional field only fails a strict parse
+        let no_pe = extended.replace("0.42~8.44~~", "0.42~n/a~~");
+        let (rt_data, diagnostics) = Tencent::parse_with(&no_pe, ParseMode::Lenient).unwrap();
+        assert_eq!(rt_data.pe, None);
+        assert_eq!(rt_data.pe_dynamic, Some(8.44));
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            (diagnostics[0].error.name, &*diagnostics[0].value),
+            ("pe", "n/a")
+        );
+        let e = Tencent::parse_with(&no_pe, ParseMode::Strict).unwrap_err();
+        assert_eq!((e.kind, e.index), (RecordErrorKind::Malformed, Some(39)));
+        assert!(Tencent::parse_with(extended, ParseMode::Strict).is_ok());
+
+        // an exact amount that doesn't parse falls back to the one in 万
+        let amount = extended.replace("/1294226951~", "/n/a~");
+        let (rt_data, diagnostics) = Tencent::parse_with(&amount, ParseMode::Lenient).unwrap();
+        assert_eq!(rt_data.total_value, dec!(1294230000));
+        assert_eq!(diagnostics[0].error.index, Some(35));
+        let e = Tencent::parse_with(&amount, ParseMode::Strict).unwrap_err();
+        assert_eq!((e.kind, e.index), (RecordErrorKind::Malformed, Some(35)));
+
+        // an HK record too short to carry its currency is in the market's
+        let hk = extended.replace("sz000001", "hk00700");
+        let (rt_data, diagnostics) = Tencent::parse_with(&hk, ParseMode::Lenient).unwrap();
+        assert_eq!(rt_data.currency, "HKD");
+        assert_eq!(
+            diagnostics[0].error.to_string(),
+            "missing currency at byte 344"
+        );
+        let e = Tencent::parse_with(&hk, ParseMode::Strict).unwrap_err();
+        assert_eq!((e.kind, e.name), (RecordErrorKind::Missing, "currency"));
+
+        // values that overflow once converted to base units are malformed
+        let lots = extended.replace("~821772~381881~", "~922337203685477581~381881~");
+        let e = Tencent::parse(&lots).unwrap_err();
+        assert_eq!((e.kind, e.index), (RecordErrorKind::Malformed, Some(6)));
+        let yi = extended.replace("~3068.08~", "~79228162514264337593543950335~");
+        let (rt_data, diagnostics) = Tencent::parse_with(&yi, ParseMode::Lenient).unwrap();
+        assert_eq!(rt_data.market_value, None);
+        assert_eq!(diagnostics[0].error.name, "market_value");
+        let e = Tencent::parse_with(&yi, ParseMode::Strict).unwrap_err();
+        assert_eq!((e.kind, e.index), (RecordErrorKind::Malformed, Some(45)));
+
+        let short = Tencent::parse("v_sz000001=\"51~平安银行~000001~15.81\"").unwrap_err();
+        assert_eq!(short.to_string(), "missing field 4 (close) at byte 40");
+
+        // every prefix of a good record fails cleanly
+        for (end, _) in extended.char_indices() {
+            assert!(Tencent::parse(&extended[..end]).is_err(), "{}", end);
+        }
+    }
+
+    #[test]
+    fn test_foreign_records() {
+        let hk = "v_hk00700=\"100~腾讯控股~00700~325.000~331.800~330.000~14753389.0~0~0~325.000~0~\
+                  0~0~0~0~0~0~0~0~325.200~0~0~0~0~0~0~0~0~0~14753389.0~2022/04/19 16:08:10~-6.800~\
+                  -2.05~333.800~324.600~325.000~14753389.0~4846934318.920~0~9.26~~0~0~2.77~31216.9\
+                  347~31216.9347~TENCENT~0.89~471.595~297.000~0.41~0~0~0~0~0~0~9.95~2.07~0.15~100~\
+                  -18.75~-23.47~GP~27.92~11.18~-5.08~-10.79~-25.12~9605379350.00~9605379350.00~9.9\
+                  5~8.466~325.893~-30.80~HKD~1~30\"";
+        let us = "v_usAAPL=\"200~苹果~AAPL.OQ~165.07~166.42~165.02~67723833~0~0~165.07~0~0~0~0~0~0\
+                  ~0~0~0~165.08~0~0~0~0~0~0~0~0~0~~2022-04-19 16:00:02~-1.35~-0.81~166.12~164.10~U\
+                  SD~67723833~11157227680~0.41~27.35~~0~0~1.21~26941.25~26941.25~Apple Inc.~0.52~1\
+                  82.94~129.04~0.76~0~0~0~0~0~0~~~~100~1.28~-5.87~GP~-2.32~-7.85~-3.48~-1.52~12.81\
+                  ~16319441000~16319441000~43.60~27.46~165.12~-2.66~~\"";
+
+        let (hk, diagnostics) = Tencent::parse_with(hk, ParseMode::Strict).unwrap();
+        assert!(diagnostics.is_empty());
+        let hk_time = hk.datatime.unwrap();
+        assert_eq!(hk_time.to_rfc3339(), "2022-04-19T16:08:10+08:00");
+        assert_eq!(hk.volume, 14753389);
+        assert_eq!(hk.currency, "HKD");
+        assert_eq!(hk.total_value, dec!(4846934318.920));
+        assert_eq!(
+            (hk.high_52w, hk.low_52w),
+            (Some(dec!(471.595)), Some(dec!(297)))
+        );
+        assert_eq!(hk.high_limit, Decimal::ZERO);
+        assert!(hk.recent_trades.is_empty());
+        assert_eq!(
+            hk.vendor_fields[&46],
+            FieldValue::Text("TENCENT".to_string())
+        );
+
+        let (us, diagnostics) = Tencent::parse_with(us, ParseMode::Strict).unwrap();
+        assert!(diagnostics.is_empty());
+        let us_time = us.datatime.unwrap();
+        assert_eq!(us_time.to_rfc3339(), "2022-04-19T16:00:02-04:00");
+        assert_eq!(us.currency, "USD");
+        assert_eq!(us.total_value, dec!(11157227680));
+        assert!(!us.vendor_fields.contains_key(&35));
+        // 08:08:10 and 20:00:02 UTC
+        assert_eq!((us_time - hk_time).num_seconds(), 11 * 3600 + 51 * 60 + 52);
+
+        for stamp in [
+            "2022/04/19 16:08",
+            "2022-04/19 16:08:10",
+            "2022/04/19T16:08:10",
+            "20220419 160810",
+        ] {
+            assert_eq!(Tencent::stamp(stamp), None, "{}", stamp);
+        }
     }
 }
@@ -0,0 +1,214 @@
+//! What to do with fields that don't parse, and logging them without
+//! flooding the log on every poll.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    str::FromStr,
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use log::warn;
+
+use super::grammar::{FromField, Record, RecordError, RecordErrorKind};
+use crate::error::RtError;
+
+/// How often per symbol field diagnostics reach the log.
+pub const WARN_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ParseMode {
+    /// Any malformed field fails the record.
+    Strict,
+    /// Optional fields that don't parse are left at their default and
+    /// reported as diagnostics.
+    #[default]
+    Lenient,
+}
+
+/// An optional field left at its default, and why.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub error: RecordError,
+    /// The text that didn't parse.
+    pub value: String,
+}
+
+impl FromStr for ParseMode {
+    type Err = RtError;
+
+    fn from_str(s: &str) -> Result<ParseMode, RtError> {
+        match s {
+            "strict" => Ok(ParseMode::Strict),
+            "lenient" => Ok(ParseMode::Lenient),
+            _ => Err(RtError::Config(format!("unknown parse mode {:?}", s))),
+        }
+    }
+}
+
+/// Collects the diagnostics of one record as its fields are read.
+pub(crate) struct Checker<'r, 'a> {
+    record:          &'r Record<'a>,
+    mode:            ParseMode,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl<'r, 'a> Checker<'r, 'a> {
+    pub fn new(record: &'r Record<'a>, mode: ParseMode) -> Self {
+        Checker {
+            record,
+            mode,
+            diagnostics: vec![],
+        }
+    }
+
+    /// Field `index`, `None` if the record is too short or the field blank.
+    pub fn optional<T: FromField>(&mut self, index: usize) -> Result<Option<T>, RecordError> {
+        if self.record.field(index).trim().is_empty() {
+            return Ok(None);
+        }
+
+        match self.record.required(index) {
+            Ok(v) => Ok(Some(v)),
+            Err(e) => self.defaulted(e).map(|_| None),
+        }
+    }
+
+    /// `optional`, converted by `convert`. A conversion that fails, such as an
+    /// overflow, counts as a malformed field.
+    pub fn optional_with<T: FromField, U>(
+        &mut self,
+        index: usize,
+        convert: impl FnOnce(T) -> Option<U>,
+    ) -> Result<Option<U>, RecordError> {
+        match self.optional(index)?.map(convert) {
+            Some(None) => self.defaulted(self.record.malformed(index)).map(|_| None),
+            converted => Ok(converted.flatten()),
+        }
+    }
+
+    /// Records that the field `error` is about was left at its default, or
+    /// fails in strict mode.
+    pub fn defaulted(&mut self, error: RecordError) -> Result<(), RecordError> {
+        if self.mode == ParseMode::Strict {
+            return Err(error);
+        }
+
+        let value = error
+            .index
+            .map(|i| self.record.field(i))
+            .unwrap_or_default();
+        self.diagnostics.push(Diagnostic {
+            value: value.to_string(),
+            error,
+        });
+        Ok(())
+    }
+}
+
+/// Aggregates diagnostics per symbol, so each symbol logs at most once an
+/// interval with counts of everything since its last line.
+#[derive(Debug)]
+pub struct WarnLimiter {
+    interval: Duration,
+    symbols:  HashMap<String, Pending>,
+}
+
+#[derive(Debug, Default)]
+struct Pending {
+    logged: Option<Instant>,
+    counts: BTreeMap<String, usize>,
+}
+
+impl WarnLimiter {
+    pub fn new(interval: Duration) -> WarnLimiter {
+        WarnLimiter {
+            interval,
+            symbols: HashMap::new(),
+        }
+    }
+
+    /// Adds `errors` for `code`, returning the line to log if one is due.
+    pub fn add<'e>(
+        &mut self,
+        code: &str,
+        errors: impl IntoIterator<Item = &'e RecordError>,
+        now: Instant,
+    ) -> Option<String> {
+        let pending = self.symbols.entry(code.to_string()).or_default();
+        // by field, offsets move with the name's length
+        for error in errors {
+            let key = match error.kind {
+                RecordErrorKind::Framing => error.kind.to_string(),
+                _ => format!("{} {}", error.kind, error.name),
+            };
+            *pending.counts.entry(key).or_default() += 1;
+        }
+
+        let due = pending
+            .logged
+            .is_none_or(|logged| now.duration_since(logged) >= self.interval);
+        if !due || pending.counts.is_empty() {
+            return None;
+        }
+
+        pending.logged = Some(now);
+        let counts = std::mem::take(&mut pending.counts);
+        let summary = counts
+            .iter()
+            .map(|(key, n)| match n {
+                1 => key.clone(),
+                n => format!("{} (x{})", key, n),
+            })
+            .collect::<Vec<_>>();
+        Some(format!("{}: {}", code, summary.join(", ")))
+    }
+}
+
+/// Logs `errors` for `code` through a process-wide `WarnLimiter`.
+pub fn report<'e>(code: &str, errors: impl IntoIterator<Item = &'e RecordError>) {
+    static LIMITER: OnceLock<Mutex<WarnLimiter>> = OnceLock::new();
+
+    let mut errors = errors.into_iter().peekable();
+    if errors.peek().is_none() {
+        return;
+    }
+
+    let limiter = LIMITER.get_or_init(|| Mutex::new(WarnLimiter::new(WARN_INTERVAL)));
+    let line = match limiter.lock() {
+        Ok(mut limiter) => limiter.add(code, errors, Instant::now()),
+        Err(_) => return,
+    };
+    if let Some(line) = line {
+        warn!("{}", line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_warn_limiter() {
+        let record = Record::parse("v_sz000001=\"51~平安银行~000001~x~y\"").unwrap();
+        let now = record.required::<f32>(3).unwrap_err();
+        let close = record.required::<f32>(4).unwrap_err();
+
+        let start = Instant::now();
+        let mut limiter = WarnLimiter::new(Duration::from_secs(60));
+        assert_eq!(
+            limiter.add("sz000001", [&now], start),
+            Some("sz000001: malformed now".to_string())
+        );
+
+        // held back, then summed up once the interval is over
+        let later = start + Duration::from_secs(1);
+        assert_eq!(limiter.add("sz000001", [&now, &close], later), None);
+        assert_eq!(limiter.add("sz000001", [&now], later), None);
+        assert!(limiter.add("sz000002", [&now], later).is_some());
+        assert_eq!(
+            limiter.add("sz000001", [], start + Duration::from_secs(60)),
+            Some("sz000001: malformed close, malformed now (x2)".to_string())
+        );
+    }
+}
@@ -0,0 +1,545 @@
+//! The shape of a Tencent quote record:
+//!
+//! ```text
+//! record = "v_" code "=" '"' field *( "~" field ) '"' [ ";" ]
+//! field  = *( any char but "~" and '"' )
+//! ```
+//!
+//! Parsing never panics; every failure names the field and byte offset.
+
+use std::fmt;
+
+use rust_decimal::Decimal;
+
+use nom::{
+    bytes::complete::{tag, take_while1},
+    character::complete::{char, multispace0},
+    combinator::{eof, opt},
+    error::{Error, ErrorKind},
+    sequence::{delimited, terminated, tuple},
+    IResult, Offset,
+};
+
+/// Fields kept per record, the longest seen has 88. Fields past this are
+/// counted but not kept, so splitting never allocates.
+pub const MAX_FIELDS: usize = 96;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordErrorKind {
+    /// Not `v_<code>="..."`.
+    Framing,
+    /// The record ends before the field.
+    Missing,
+    /// The field doesn't hold a value of its type.
+    Malformed,
+}
+
+/// Where and why a record failed to parse.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecordError {
+    pub kind:   RecordErrorKind,
+    /// Index of the `~` separated field, `None` for the framing around them
+    /// or a value no field holds.
+    pub index:  Option<usize>,
+    pub name:   &'static str,
+    /// Byte offset into the record.
+    pub offset: usize,
+}
+
+/// A record split into its fields, borrowing from the input.
+#[derive(Clone, Debug)]
+pub struct Record<'a> {
+    input:    &'a str,
+    pub code: &'a str,
+    body:     &'a str,
+    fields:   Fields,
+}
+
+/// Where the `~` separators of a body are, so a field is sliced out only when
+/// it is read.
+#[derive(Clone, Debug)]
+struct Fields {
+    /// Byte offset of each separator, the last slot taking those past
+    /// `MAX_FIELDS`.
+    seps: [u32; MAX_FIELDS + 1],
+    len:  usize,
+}
+
+impl Fields {
+    fn split(body: &str) -> Self {
+        const ONES: u64 = 0x0101_0101_0101_0101;
+        const LOW7: u64 = 0x7f7f_7f7f_7f7f_7f7f;
+        const TILDES: u64 = ONES * b'~' as u64;
+
+        let mut fields = Fields {
+            seps: [0; MAX_FIELDS + 1],
+            len:  1,
+        };
+
+        // eight bytes at a time: a byte of `word` is zero where the body has a
+        // `~`, and the mask sets the high bit of exactly those bytes
+        let mut chunks = body.as_bytes().chunks_exact(8);
+        for (i, chunk) in (&mut chunks).enumerate() {
+            let word = u64::from_le_bytes(chunk.try_into().unwrap_or_default()) ^ TILDES;
+            let mut zeros = !(((word & LOW7) + LOW7) | word | LOW7);
+            while zeros != 0 {
+                fields.push(i * 8 + zeros.trailing_zeros() as usize / 8);
+                zeros &= zeros - 1;
+            }
+        }
+
+        let base = body.len() - chunks.remainder().len();
+        for (i, b) in chunks.remainder().iter().enumerate() {
+            if *b == b'~' {
+                fields.push(base + i);
+            }
+        }
+        fields
+    }
+
+    fn push(&mut self, sep: usize) {
+        self.seps[(self.len - 1).min(MAX_FIELDS)] = sep as u32;
+        self.len += 1;
+    }
+
+    /// Field `index` of `body`. `~` is ASCII, so every split lands on a char
+    /// boundary.
+    fn get<'a>(&self, body: &'a str, index: usize) -> Option<&'a str> {
+        if index >= self.len.min(MAX_FIELDS) {
+            return None;
+        }
+
+        let start = match index {
+            0 => 0,
+            i => self.seps[i - 1] as usize + 1,
+        };
+        let end = match index + 1 < self.len {
+            true => self.seps[index] as usize,
+            false => body.len(),
+        };
+        body.get(start..end)
+    }
+}
+
+impl fmt::Display for RecordErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RecordErrorKind::Framing => "malformed record",
+            RecordErrorKind::Missing => "missing",
+            RecordErrorKind::Malformed => "malformed",
+        })
+    }
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.index {
+            Some(index) => write!(
+                f,
+                "{} field {} ({}) at byte {}",
+                self.kind, index, self.name, self.offset
+            ),
+            None if self.kind == RecordErrorKind::Framing => {
+                write!(f, "{} at byte {}", self.kind, self.offset)
+            }
+            None => write!(f, "{} {} at byte {}", self.kind, self.name, self.offset),
+        }
+    }
+}
+
+impl std::error::Error for RecordError {}
+
+/// A type a field reads as. Nearly every field is a plain decimal such as
+/// `-12.34`, which skips the general `FromStr` parsers.
+pub trait FromField: Sized {
+    fn from_field(field: &str) -> Option<Self>;
+}
+
+/// The sign, digits and scale of a plain decimal of up to 18 digits, with a
+/// digit on both sides of any point. `None` for anything else, which is left
+/// to `FromStr`.
+fn plain(field: &str) -> Option<(bool, u64, u32)> {
+    let (negative, digits) = match field.as_bytes() {
+        [b'-', digits @ ..] => (true, digits),
+        digits => (false, digits),
+    };
+    if digits.is_empty() || digits.len() > 18 {
+        return None;
+    }
+
+    let mut mantissa = 0;
+    let mut point = None;
+    for (i, c) in digits.iter().enumerate() {
+        match c {
+            b'0'..=b'9' => mantissa = mantissa * 10 + u64::from(c - b'0'),
+            b'.' if point.is_none() && i > 0 && i + 1 < digits.len() => point = Some(i),
+            _ => return None,
+        }
+    }
+    let scale = point.map_or(0, |i| digits.len() - i - 1);
+
+    Some((negative, mantissa, scale as u32))
+}
+
+impl FromField for Decimal {
+    fn from_field(field: &str) -> Option<Decimal> {
+        match plain(field) {
+            // `-0` keeps its sign. 18 digits fit in the low two words, and
+            // `from_parts` skips the checks of `Decimal::new`, which cost
+            // more than the parse
+            Some((negative, mantissa, scale)) if !(negative && mantissa == 0) => {
+                let (lo, mid) = (mantissa as u32, (mantissa >> 32) as u32);
+                Some(Decimal::from_parts(lo, mid, 0, negative, scale))
+            }
+            _ => field.parse().ok(),
+        }
+    }
+}
+
+const POWERS_OF_TEN: [f64; 11] = [1e0, 1e1, 1e2, 1e3, 1e4, 1e5, 1e6, 1e7, 1e8, 1e9, 1e10];
+
+impl FromField for f32 {
+    fn from_field(field: &str) -> Option<f32> {
+        // both operands are exact in an `f32`, so the `f64` quotient rounds to
+        // the same `f32` as parsing would
+        match plain(field) {
+            Some((negative, mantissa, scale)) if mantissa < 1 << 24 && scale <= 10 => {
+                let value = (mantissa as f64 / POWERS_OF_TEN[scale as usize]) as f32;
+                Some(if negative { -value } else { value })
+            }
+            _ => field.parse().ok(),
+        }
+    }
+}
+
+impl FromField for i64 {
+    fn from_field(field: &str) -> Option<i64> {
+        match plain(field) {
+            Some((negative, mantissa, 0)) => {
+                let mantissa = mantissa as i64;
+                Some(if negative { -mantissa } else { mantissa })
+            }
+            _ => field.parse().ok(),
+        }
+    }
+}
+
+/// The code and the `~` separated body of a record.
+fn framing(input: &str) -> IResult<&str, (&str, &str)> {
+    let code = take_while1(|c: char| c.is_ascii_alphanumeric() || "_.-".contains(c));
+    let (rest, (code, body, ..)) = tuple((
+        delimited(tag("v_"), code, tag("=\"")),
+        terminated(quoted, char('"')),
+        opt(char(';')),
+        multispace0,
+        eof,
+    ))(input)?;
+
+    Ok((rest, (code, body)))
+}
+
+/// Up to the closing quote, an unterminated body failing at its end where the
+/// quote is missing. Bodies run to hundreds of bytes, so this is a `memchr`.
+fn quoted(input: &str) -> IResult<&str, &str> {
+    match memchr::memchr(b'"', input.as_bytes()) {
+        Some(end) => Ok((&input[end..], &input[..end])),
+        None => Err(nom::Err::Error(Error::new(
+            &input[input.len()..],
+            ErrorKind::TakeUntil,
+        ))),
+    }
+}
+
+impl<'a> Record<'a> {
+    pub fn parse(input: &'a str) -> Result<Record<'a>, RecordError> {
+        match framing(input) {
+            Ok((_, (code, body))) => Ok(Record {
+                input,
+                code,
+                body,
+                fields: Fields::split(body),
+            }),
+            Err(nom::Err::Error(e) | nom::Err::Failure(e)) => Err(RecordError {
+                kind:   RecordErrorKind::Framing,
+                index:  None,
+                name:   "record",
+                offset: input.offset(e.input),
+            }),
+            Err(nom::Err::Incomplete(_)) => Err(RecordError {
+                kind:   RecordErrorKind::Framing,
+                index:  None,
+                name:   "record",
+                offset: input.len(),
+            }),
+        }
+    }
+
+    /// Number of fields, including any past `MAX_FIELDS`.
+    pub fn len(&self) -> usize {
+        self.fields.len
+    }
+
+    /// Whether the body is blank, as in `v_sz000001=""`, which still counts
+    /// as one empty field.
+    pub fn is_empty(&self) -> bool {
+        self.body.is_empty()
+    }
+
+    /// The fields up to `MAX_FIELDS`.
+    pub fn fields(&self) -> impl Iterator<Item = &'a str> + '_ {
+        (0..self.fields.len.min(MAX_FIELDS)).map(|i| self.field(i))
+    }
+
+    /// Field `index`, empty if the record stops short of it.
+    pub fn field(&self, index: usize) -> &'a str {
+        self.get(index).unwrap_or_default()
+    }
+
+    fn get(&self, index: usize) -> Option<&'a str> {
+        self.fields.get(self.body, index)
+    }
+
+    /// Field `index` as a `T`.
+    pub fn required<T: FromField>(&self, index: usize) -> Result<T, RecordError> {
+        match self.get(index) {
+            Some(v) => T::from_field(v).ok_or_else(|| self.malformed(index)),
+            None => Err(self.missing(index)),
+        }
+    }
+
+    /// Field `index` as a `T`, converted by `convert`. A conversion that fails,
+    /// such as an overflow, counts as a malformed field.
+    pub fn required_with<T: FromField, U>(
+        &self,
+        index: usize,
+        convert: impl FnOnce(T) -> Option<U>,
+    ) -> Result<U, RecordError> {
+        convert(self.required(index)?).ok_or_else(|| self.malformed(index))
+    }
+
+    /// Field `index` as a `T`, `None` if the record is too short or the field
+    /// is blank or malformed.
+    pub fn optional<T: FromField>(&self, index: usize) -> Option<T> {
+        match self.get(index)?.trim() {
+            "" => None,
+            v => T::from_field(v),
+        }
+    }
+
+    /// The error for field `index` not holding a value of its type.
+    pub fn malformed(&self, index: usize) -> RecordError {
+        match self.get(index) {
+            Some(v) => RecordError {
+                kind:   RecordErrorKind::Malformed,
+                index:  Some(index),
+                name:   field_name(index),
+                offset: self.input.offset(v),
+            },
+            None => self.missing(index),
+        }
+    }
+
+    /// The error for a record that ends before field `index`.
+    pub fn missing(&self, index: usize) -> RecordError {
+        RecordError {
+            kind:   RecordErrorKind::Missing,
+            index:  Some(index),
+            name:   field_name(index),
+            offset: self.end(),
+        }
+    }
+
+    /// The error for a value no field of the record holds, such as the
+    /// currency.
+    pub fn absent(&self, name: &'static str) -> RecordError {
+        RecordError {
+            kind: RecordErrorKind::Missing,
+            index: None,
+            name,
+            offset: self.end(),
+        }
+    }
+
+    /// Just before the closing quote.
+    fn end(&self) -> usize {
+        self.input.offset(self.body) + self.body.len()
+    }
+}
+
+/// What Tencent puts in field `index`, named after the `ItemData` field it
+/// ends up in where there is one.
+pub fn field_name(index: usize) -> &'static str {
+    match index {
+        0 => "market",
+        1 => "name",
+        2 => "code",
+        3 => "now",
+        4 => "close",
+        5 => "open",
+        6 => "volume",
+        7 => "bid_volume",
+        8 => "ask_volume",
+        9 | 11 | 13 | 15 | 17 => "bid_price",
+        10 | 12 | 14 | 16 | 18 => "bid_lots",
+        19 | 21 | 23 | 25 | 27 => "ask_price",
+        20 | 22 | 24 | 26 | 28 => "ask_lots",
+        29 => "recent_trades",
+        30 => "datatime",
+        31 => "gain_amount",
+        32 => "gain_percentage",
+        33 | 41 => "high",
+        34 | 42 => "low",
+        35 => "price_volume_amount",
+        36 => "volume_lots",
+        37 => "amount_wan",
+        38 => "turnover",
+        39 => "pe",
+        43 => "amplitude",
+        44 => "traded_market_value",
+        45 => "market_value",
+        46 => "pb",
+        47 => "high_limit",
+        48 => "low_limit",
+        49 => "quantity_relative_ratio",
+        50 => "entrust_different",
+        51 => "average_price",
+        52 => "pe_dynamic",
+        53 => "pe_static",
+        57 => "amount_wan_exact",
+        61 => "vendor_type",
+        62 => "change_5d",
+        63 => "change_10d",
+        64 => "change_20d",
+        65 => "change_60d",
+        66 => "change_120d",
+        67 => "high_52w",
+        68 => "low_52w",
+        72 => "circulating_shares",
+        73 => "total_shares",
+        _ => "unknown",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_errors() {
+        let record = Record::parse("v_sz000001=\"51~平安银行~000001~15.81~x\";\n").unwrap();
+        assert_eq!(record.code, "sz000001");
+        assert_eq!(record.len(), 5);
+        assert!(!record.is_empty());
+        assert_eq!(record.field(1), "平安银行");
+        assert_eq!(record.field(9), "");
+        assert_eq!(record.required::<f32>(3), Ok(15.81));
+
+        let malformed = record.required::<f32>(4).unwrap_err();
+        assert_eq!(malformed.kind, RecordErrorKind::Malformed);
+        assert_eq!((malformed.index, malformed.name), (Some(4), "close"));
+        assert_eq!(malformed.offset, 41);
+        assert_eq!(
+            malformed.to_string(),
+            "malformed field 4 (close) at byte 41"
+        );
+
+        let missing = record.required::<f32>(5).unwrap_err();
+        assert_eq!(missing.kind, RecordErrorKind::Missing);
+        assert_eq!((missing.name, missing.offset), ("open", 42));
+
+        let blank = Record::parse("v_sz000001=\"\"").unwrap();
+        assert!(blank.is_empty());
+        assert_eq!((blank.len(), blank.field(0)), (1, ""));
+
+        for (input, offset) in [
+            ("", 0),
+            ("sz000001=\"1\"", 0),
+            ("v_sz000001\"1\"", 10),
+            // where the closing quote should be
+            ("v_sz000001=\"1", 13),
+            ("v_sz000001=\"1\" trailing", 15),
+        ] {
+            let e = Record::parse(input).unwrap_err();
+            assert_eq!(
+                (e.kind, e.offset),
+                (RecordErrorKind::Framing, offset),
+                "{}",
+                input
+            );
+        }
+    }
+    #[test]
+    fn test_split() {
+        // separators on either side of every eight byte boundary, runs of
+        // them and multi-byte chars in between
+        for body in [
+            "",
+            "~",
+            "a~~b",
+            "1234567~9",
+            "12345678~",
+            "~~~~~~~~~~~",
+            "平安~银行~~x",
+        ] {
+            for pad in 0..9 {
+                let body = format!("{}{}", "-".repeat(pad), body);
+                let input = format!("v_sz000001=\"{}\"", body);
+                let record = Record::parse(&input).unwrap();
+                let split = body.split('~').collect::<Vec<_>>();
+                assert_eq!(record.fields().collect::<Vec<_>>(), split, "{:?}", body);
+                assert_eq!(record.len(), split.len());
+            }
+        }
+
+        // fields past `MAX_FIELDS` are counted, not kept
+        let body = (0..100)
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>()
+            .join("~");
+        let input = format!("v_sz000001=\"{}\"", body);
+        let record = Record::parse(&input).unwrap();
+        assert_eq!(record.len(), 100);
+        assert_eq!(record.fields().count(), MAX_FIELDS);
+        assert_eq!(record.field(MAX_FIELDS - 1), "95");
+        assert_eq!(record.field(MAX_FIELDS), "");
+    }
+    #[test]
+    fn test_from_field() {
+        // the fast paths agree with `FromStr`, and hand it everything else
+        for v in [
+            "15.81",
+            "-0.57",
+            "0",
+            "-0",
+            "-0.00",
+            "325.000",
+            "821772",
+            "999999999999999999",
+            "9999999999999999999",
+            "16777215.5",
+            "0.0000000001",
+            "1e5",
+            "+1",
+            "1.",
+            ".5",
+            "1.2.3",
+            "-",
+            "",
+            " 1",
+        ] {
+            let decimal = v.parse::<Decimal>().ok();
+            assert_eq!(Decimal::from_field(v), decimal, "{:?}", v);
+            let sign = |d: Option<Decimal>| d.map(|d| d.is_sign_negative());
+            assert_eq!(sign(Decimal::from_field(v)), sign(decimal), "{:?}", v);
+            assert_eq!(f32::from_field(v), v.parse().ok(), "{:?}", v);
+            assert_eq!(i64::from_field(v), v.parse().ok(), "{:?}", v);
+        }
+
+        for mantissa in (0..1 << 24).step_by(997) {
+            for scale in 0..=10 {
+                let v = Decimal::new(mantissa, scale).to_string();
+                assert_eq!(f32::from_field(&v), v.parse().ok(), "{}", v);
+            }
+        }
+    }
+}
@@ -0,0 +1,329 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+use encoding_rs::{Encoding, UTF_8};
+use log::warn;
+use reqwest::Response;
+
+use super::{diagnostics, ParseMode, Tencent};
+use crate::{error::BlockKind, realtime_data::QuoteOutcome, status::TradingStatus};
+
+/// Bytes of a body looked at before giving up on telling what it is.
+const SNIFF_LIMIT: usize = 8192;
+
+/// Phrases of the plain text notices sent instead of quotes when throttled.
+const RATE_LIMIT_PHRASES: [&str; 5] = ["频繁", "稍后", "too many", "rate limit", "limit exceeded"];
+
+/// What the start of a response body looks like.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Sniffed {
+    Quotes,
+    Blocked(BlockKind),
+    /// Not enough of the body yet.
+    Undecided,
+}
+
+/// Cuts a response body into `;` terminated records as its chunks arrive and
+/// maps each one back to the symbol it was requested as.
+///
+/// `;` is never part of a multi-byte GBK or UTF-8 character, so the raw bytes
+/// are split first and each record decoded on its own.
+pub struct ResponseParser {
+    stocks_list: Vec<String>,
+    encoding:    &'static Encoding,
+    mode:        ParseMode,
+    buf:         Vec<u8>,
+    /// The record being parsed, decoded. Reused so decoding doesn't allocate
+    /// per record.
+    text:        String,
+    /// Records seen so far, for attributing one by position.
+    index:       usize,
+    answered:    HashSet<String>,
+}
+
+impl ResponseParser {
+    pub fn new(stocks_list: &[&str], encoding: &'static Encoding) -> ResponseParser {
+        ResponseParser {
+            stocks_list: stocks_list.iter().map(|s| s.to_string()).collect(),
+            encoding,
+            mode: ParseMode::default(),
+            buf: vec![],
+            text: String::new(),
+            index: 0,
+            answered: HashSet::new(),
+        }
+    }
+
+    pub fn with_mode(mut self, mode: ParseMode) -> ResponseParser {
+        self.mode = mode;
+        self
+    }
+
+    /// For a body that is already text.
+    pub fn utf8(stocks_list: &[&str]) -> ResponseParser {
+        ResponseParser::new(stocks_list, UTF_8)
+    }
+
+    /// Outcomes of the records `chunk` completes, received at `received_at`.
+    pub fn push(
+        &mut self,
+        chunk: &[u8],
+        received_at: DateTime<Utc>,
+    ) -> Vec<(String, QuoteOutcome)> {
+        self.buf.extend_from_slice(chunk);
+        let end = match self.buf.iter().rposition(|b| *b == b';') {
+            Some(end) => end,
+            None => return vec![],
+        };
+
+        let mut buf = std::mem::take(&mut self.buf);
+        let records = &buf[..end];
+        let mut outcomes = Vec::with_capacity(memchr::memchr_iter(b';', records).count() + 1);
+        outcomes.extend(
+            records
+                .split(|b| *b == b';')
+                .filter_map(|record| self.record(record, received_at)),
+        );
+        buf.drain(..=end);
+        self.buf = buf;
+        outcomes
+    }
+
+    /// Outcomes of an unterminated last record, and `NotFound` for every
+    /// symbol the body didn't answer.
+    pub fn finish(&mut self, received_at: DateTime<Utc>) -> Vec<(String, QuoteOutcome)> {
+        let rest = std::mem::take(&mut self.buf);
+        let mut outcomes = self
+            .record(&rest, received_at)
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        for symbol in &self.stocks_list {
+            if self.answered.insert(symbol.clone()) {
+                outcomes.push((symbol.clone(), QuoteOutcome::NotFound));
+            }
+        }
+
+        outcomes
+    }
+
+    fn record(
+        &mut self,
+        bytes: &[u8],
+        received_at: DateTime<Utc>,
+    ) -> Option<(String, QuoteOutcome)> {
+        let mut decoder = self.encoding.new_decoder_without_bom_handling();
+        self.text.clear();
+        self.text.reserve(
+            decoder
+                .max_utf8_buffer_length(bytes.len())
+                .unwrap_or_default(),
+        );
+        let _ = decoder.decode_to_string(bytes, &mut self.text, true);
+        let record = self.text.trim();
+        if record.is_empty() {
+            return None;
+        }
+
+        let idx = self.index;
+        self.index += 1;
+
+        // records come back in request order, so the symbol at this position
+        // is tried first, and an unknown code (`v_pv_none_match`) or one that
+        // can't be matched goes by position, unless that symbol was answered
+        // already
+        let code = record
+            .strip_prefix("v_")
+            .and_then(|i| i.split('=').next())
+            .unwrap_or_default();
+        let in_order = self
+            .stocks_list
+            .get(idx)
+            .filter(|s| s.eq_ignore_ascii_case(code));
+        let symbol = in_order
+            .or_else(|| {
+                self.stocks_list
+                    .iter()
+                    .find(|s| s.eq_ignore_ascii_case(code))
+            })
+            .or_else(|| {
+                self.stocks_list
+                    .get(idx)
+                    .filter(|s| !self.answered.contains(*s))
+            });
+        let symbol = match symbol {
+            Some(symbol) => symbol.clone(),
+            None => {
+                warn!("unexpected record in response: {}", record);
+                return None;
+            }
+        };
+        self.answered.insert(symbol.clone());
+
+        if code == "pv_none_match" {
+            return Some((symbol, QuoteOutcome::NotFound));
+        }
+
+        let outcome = match Tencent::parse_with(record, self.mode) {
+            Ok((mut rt_data, defaulted)) => {
+                diagnostics::report(code, defaulted.iter().map(|d| &d.error));
+                rt_data.received_at = Some(received_at);
+                if Tencent::is_suspended(&rt_data, received_at) {
+                    rt_data.status = TradingStatus::Suspended;
+                    QuoteOutcome::Suspended(rt_data)
+                } else {
+                    QuoteOutcome::Quote(rt_data)
+                }
+            }
+            Err(e) => {
+                diagnostics::report(code, [&e]);
+                QuoteOutcome::ParseError {
+                    details: e.to_string(),
+                }
+            }
+        };
+
+        Some((symbol, outcome))
+    }
+}
+
+/// Tells quotes from block pages and error bodies by the start of the body,
+/// `head`. `complete` when `head` is the whole body.
+pub fn sniff(head: &[u8], encoding: &'static Encoding, complete: bool) -> Sniffed {
+    let start = head.iter().position(|b| !b.is_ascii_whitespace());
+    let body = match start {
+        Some(start) => &head[start..],
+        None if complete => return Sniffed::Blocked(BlockKind::Empty),
+        None => return Sniffed::Undecided,
+    };
+
+    if body.starts_with(b"v_") {
+        // a whole record has to decode cleanly
+        let record = match body.iter().position(|b| *b == b';') {
+            Some(end) => &body[..end],
+            None if complete || body.len() >= SNIFF_LIMIT => body,
+            None => return Sniffed::Undecided,
+        };
+        return match encoding.decode_without_bom_handling(record) {
+            (_, true) => Sniffed::Blocked(BlockKind::Charset),
+            (_, false) => Sniffed::Quotes,
+        };
+    }
+    if body.len() < 2 && !complete {
+        return Sniffed::Undecided;
+    }
+    if body.starts_with(b"<") {
+        return Sniffed::Blocked(BlockKind::Html);
+    }
+
+    let text = encoding.decode_without_bom_handling(body).0.to_lowercase();
+    if RATE_LIMIT_PHRASES.iter().any(|p| text.contains(p)) {
+        Sniffed::Blocked(BlockKind::RateLimited)
+    } else {
+        Sniffed::Blocked(BlockKind::Unrecognized)
+    }
+}
+
+/// Up to `SNIFF_LIMIT` bytes of the body of `response`, and whether that is
+/// all of it. A body that fails to read stops short.
+pub async fn head(mut response: Response) -> (Vec<u8>, bool) {
+    let mut head = vec![];
+    while head.len() < SNIFF_LIMIT {
+        match response.chunk().await {
+            Ok(Some(chunk)) => head.extend_from_slice(&chunk),
+            Ok(None) => return (head, true),
+            Err(_) => break,
+        }
+    }
+
+    (head, false)
+}
+
+/// The first characters of `body`, for an error message.
+pub fn excerpt(body: &[u8], encoding: &'static Encoding) -> String {
+    let text = encoding.decode_without_bom_handling(body).0;
+    text.trim().chars().take(160).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use encoding_rs::GBK;
+
+    use super::*;
+
+    #[test]
+    fn test_chunked_records() {
+        let body = "v_sh518801=\"1~国泰申赎~518801~2.229~2.229~0.000~0~0~0~0.000~0~0.000~0~0.\
+                    000~0~0.000~0~0.000~0~0.000~0~0.000~0~0.000~0~0.000~0~0.\
+                    000~0~~20151224150221~0.000~0.00~0.000~0.000~2.230/0/0~0~0~~~~0.000~0.000~0.\
+                    00~~~0.000~2.452~2.006~\";\nv_pv_none_match=\"1\";\n";
+        let (gbk, ..) = GBK.encode(body);
+
+        // split inside the two byte characters of the name
+        let received_at = Utc.ymd(2015, 12, 25).and_hms(1, 30, 0);
+        let mut parser = ResponseParser::new(&["sh518801", "sh999999", "sz000001"], GBK);
+        let mut outcomes = vec![];
+        for chunk in gbk.chunks(7) {
+            outcomes.extend(parser.push(chunk, received_at));
+            if outcomes.is_empty() {
+                continue;
+            }
+            // the first record is out before the rest of the body arrives
+            assert_eq!(outcomes[0].0, "sh518801");
+        }
+        outcomes.extend(parser.finish(received_at));
+
+        assert_eq!(outcomes.len(), 3);
+        match &outcomes[0].1 {
+            QuoteOutcome::Suspended(rt_data) => assert_eq!(rt_data.name, "国泰申赎"),
+            other => panic!("expected suspended quote, got {:?}", other),
+        }
+        assert_eq!(outcomes[1].0, "sh999999");
+        assert!(matches!(outcomes[1].1, QuoteOutcome::NotFound));
+        assert_eq!(outcomes[2].0, "sz000001");
+        assert!(matches!(outcomes[2].1, QuoteOutcome::NotFound));
+    }
+
+    #[test]
+    fn test_out_of_order_records() {
+        // the unknown code is second, but the symbol second in the request
+        // was already answered out of order
+        let body = "v_sz000001=\"1\";v_pv_none_match=\"1\";";
+        let mut parser = ResponseParser::utf8(&["sh600000", "sz000001"]);
+        let mut outcomes = parser.push(body.as_bytes(), Utc::now());
+        outcomes.extend(parser.finish(Utc::now()));
+
+        let symbols = outcomes.iter().map(|(s, _)| s.as_str()).collect::<Vec<_>>();
+        assert_eq!(symbols, ["sz000001", "sh600000"]);
+        assert!(matches!(outcomes[0].1, QuoteOutcome::ParseError { .. }));
+        assert!(matches!(outcomes[1].1, QuoteOutcome::NotFound));
+    }
+
+    #[test]
+    fn test_sniff() {
+        use BlockKind::*;
+        use Sniffed::{Blocked, Quotes, Undecided};
+
+        let (gbk, ..) = GBK.encode("v_sz000001=\"51~平安银行~000001\";");
+        let (notice, ..) = GBK.encode("访问过于频繁，请稍后再试");
+        for (body, encoding, complete, sniffed) in [
+            (&gbk[..], GBK, true, Quotes),
+            (&gbk[..], UTF_8, true, Blocked(Charset)),
+            (&gbk[..16], GBK, false, Undecided),
+            (b"v_pv_none_match=\"1\";", UTF_8, true, Quotes),
+            (b"", UTF_8, true, Blocked(Empty)),
+            (b" \r\n", UTF_8, false, Undecided),
+            (b" \r\n", UTF_8, true, Blocked(Empty)),
+            (b"\n<!DOCTYPE html><html>", UTF_8, false, Blocked(Html)),
+            (&notice[..], GBK, true, Blocked(RateLimited)),
+            (b"Too Many Requests", UTF_8, true, Blocked(RateLimited)),
+            (b"{\"code\": -1}", UTF_8, true, Blocked(Unrecognized)),
+        ] {
+            assert_eq!(sniff(body, encoding, complete), sniffed, "{:?}", body);
+        }
+
+        let page = b"\n<html>blocked</html>";
+        assert_eq!(excerpt(page, UTF_8), "<html>blocked</html>");
+    }
+}
@@ -0,0 +1,127 @@
+//! A configurable source for the tests of everything built on `GainRTData`.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use async_trait::async_trait;
+use reqwest::StatusCode;
+
+use crate::{
+    error::{BlockKind, Result, RtError},
+    realtime_data::{ItemData, QuoteOutcome},
+    GainRTData,
+};
+
+/// Answers the codes it serves with a copy of its quote and the rest with
+/// `NotFound`, keeping track of what it was asked.
+pub struct FakeSource {
+    name:         &'static str,
+    quote:        ItemData,
+    /// `None` serves every code.
+    codes:        Option<Vec<&'static str>>,
+    fail:         bool,
+    counting:     bool,
+    pub calls:    AtomicUsize,
+    /// Every symbol list asked for, each sorted.
+    pub requests: Arc<Mutex<Vec<Vec<String>>>>,
+}
+
+impl FakeSource {
+    /// Serves every code with a default quote.
+    pub fn new(name: &'static str) -> FakeSource {
+        FakeSource {
+            name,
+            quote: ItemData::default(),
+            codes: None,
+            fail: false,
+            counting: false,
+            calls: AtomicUsize::new(0),
+            requests: Arc::default(),
+        }
+    }
+
+    /// Serves `quote`, with the code and source filled in.
+    pub fn with_quote(mut self, quote: ItemData) -> FakeSource {
+        self.quote = quote;
+        self
+    }
+
+    /// Serves only `codes`.
+    pub fn with_codes(mut self, codes: &[&'static str]) -> FakeSource {
+        self.codes = Some(codes.to_vec());
+        self
+    }
+
+    /// Fails every request with a 503.
+    pub fn failing(mut self) -> FakeSource {
+        self.fail = true;
+        self
+    }
+
+    /// Stamps each quote's `volume` with the number of earlier calls, so
+    /// successive polls tell apart.
+    pub fn counting(mut self) -> FakeSource {
+        self.counting = true;
+        self
+    }
+
+    pub fn boxed(self) -> Box<dyn GainRTData> {
+        Box::new(self)
+    }
+}
+
+#[async_trait]
+impl GainRTData for FakeSource {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    async fn quotes(
+        &self,
+        stocks_list: Vec<&str>,
+        _prefix: bool,
+    ) -> Result<HashMap<String, QuoteOutcome>> {
+        let calls = self.calls.fetch_add(1, Ordering::SeqCst);
+        let mut request = stocks_list
+            .iter()
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>();
+        request.sort();
+        self.requests.lock().unwrap().push(request);
+
+        if self.fail {
+            return Err(RtError::Blocked {
+                kind:    BlockKind::Empty,
+                status:  StatusCode::SERVICE_UNAVAILABLE,
+                url:     self.name.to_string(),
+                excerpt: String::new(),
+            });
+        }
+
+        Ok(stocks_list
+            .into_iter()
+            .map(|code| {
+                if self.codes.as_ref().is_some_and(|c| !c.contains(&code)) {
+                    return (code.to_string(), QuoteOutcome::NotFound);
+                }
+
+                let rt_data = ItemData {
+                    code: code.to_string(),
+                    source: self.name.to_string(),
+                    volume: if self.counting {
+                        calls as i64
+                    } else {
+                        self.quote.volume
+                    },
+                    ..self.quote.clone()
+                };
+                (code.to_string(), QuoteOutcome::Quote(rt_data))
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,69 @@
+//! Conversions from the units vendors quote in to the base units of
+//! `ItemData`: shares for quantities, the quote currency for amounts.
+//!
+//! Every source goes through these instead of multiplying inline, so a lot or
+//! a 亿 means the same thing everywhere.
+
+use crate::{
+    price::Decimal,
+    symbol::{InstrumentType, Market},
+};
+
+/// 万, ten thousand.
+pub const WAN: i64 = 10_000;
+
+/// 亿, a hundred million.
+pub const YI: i64 = 100_000_000;
+
+/// Units in one board lot (手) of an `instrument` on `market`, or `None`
+/// where volumes are already quoted in units. Chinese bonds, convertibles and
+/// repos trade in lots of 10, everything else in lots of 100 shares. HK lot
+/// sizes differ per stock, so HK vendors send shares.
+pub fn lot_size(market: Market, instrument: InstrumentType) -> Option<i64> {
+    use InstrumentType::*;
+
+    match (market, instrument) {
+        (Market::China, Bond | ConvertibleBond | Repo) => Some(10),
+        (Market::China, _) => Some(100),
+        (Market::HongKong | Market::UnitedStates, _) => None,
+    }
+}
+
+/// A volume quoted in lots of `instrument` on `market`, in units, `None` on
+/// overflow.
+pub fn lots_to_shares(lots: i64, market: Market, instrument: InstrumentType) -> Option<i64> {
+    lots.checked_mul(lot_size(market, instrument).unwrap_or(1))
+}
+
+/// An amount quoted in 万, in base currency units, `None` on overflow.
+pub fn wan_to_base(amount: Decimal) -> Option<Decimal> {
+    amount.checked_mul(Decimal::from(WAN))
+}
+
+/// An amount quoted in 亿, in base currency units, `None` on overflow.
+pub fn yi_to_base(amount: Decimal) -> Option<Decimal> {
+    amount.checked_mul(Decimal::from(YI))
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    #[test]
+    fn test_units() {
+        use InstrumentType::*;
+
+        assert_eq!(lots_to_shares(821772, Market::China, Stock), Some(82177200));
+        assert_eq!(lots_to_shares(821772, Market::China, Etf), Some(82177200));
+        assert_eq!(lots_to_shares(3052, Market::China, Bond), Some(30520));
+        assert_eq!(lots_to_shares(3052, Market::China, Repo), Some(30520));
+        assert_eq!(lots_to_shares(1500, Market::HongKong, Stock), Some(1500));
+        assert_eq!(wan_to_base(dec!(129422.6951)), Some(dec!(1294226951)));
+        assert_eq!(yi_to_base(dec!(3068.08)), Some(dec!(306808000000)));
+
+        assert_eq!(lots_to_shares(i64::MAX / 10, Market::China, Stock), None);
+        assert_eq!(yi_to_base(Decimal::MAX), None);
+    }
+}